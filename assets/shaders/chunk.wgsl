@@ -0,0 +1,109 @@
+#import bevy_pbr::{
+    mesh_functions::{get_world_from_local, mesh_position_local_to_world},
+    view_transformations::position_world_to_clip,
+    pbr_types::pbr_input_new,
+    pbr_functions::{apply_pbr_lighting, calculate_view, main_pass_post_lighting_processing},
+    mesh_view_bindings::view,
+}
+
+// see ChunkMaterial
+struct ChunkMaterial {
+    reflectance: f32,
+    perceptual_roughness: f32,
+    metallic: f32,
+    origin_offset: f32,
+};
+
+// darkest a voxel gets without any sky or block light
+const MIN_LIGHT: f32 = 0.02;
+
+@group(2) @binding(0) var<uniform> material: ChunkMaterial;
+@group(2) @binding(1) var block_textures: texture_2d_array<f32>;
+@group(2) @binding(2) var block_sampler: sampler;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    // x: position 6+6+6 bits, ao 3 bits, normal 3 bits, block type 7 bits (make_vertex_u32)
+    // y: uv 6+6 bits, texture layer 8 bits, sky light 4 bits, block light 4 bits (make_vertex_uv_u32)
+    @location(0) voxel: vec2<u32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) @interpolate(flat) layer: u32,
+    @location(4) ao: f32,
+    // sky and block light, from 0 to 1
+    @location(5) light: vec2<f32>,
+};
+
+// indexed by FaceDir::normal_index
+fn face_normal(index: u32) -> vec3<f32> {
+    switch index {
+        case 0u: { return vec3(-1.0, 0.0, 0.0); }
+        case 1u: { return vec3(1.0, 0.0, 0.0); }
+        case 2u: { return vec3(0.0, -1.0, 0.0); }
+        case 3u: { return vec3(0.0, 1.0, 0.0); }
+        case 4u: { return vec3(0.0, 0.0, -1.0); }
+        default: { return vec3(0.0, 0.0, 1.0); }
+    }
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let packed = vertex.voxel.x;
+    let position = vec3<f32>(
+        f32(packed & 63u),
+        f32((packed >> 6u) & 63u),
+        f32((packed >> 12u) & 63u),
+    ) + vec3(material.origin_offset);
+    let ao = (packed >> 18u) & 7u;
+    let normal = face_normal((packed >> 21u) & 7u);
+
+    let uv = vertex.voxel.y;
+
+    let world_from_local = get_world_from_local(vertex.instance_index);
+    var out: VertexOutput;
+    out.world_position = mesh_position_local_to_world(world_from_local, vec4(position, 1.0));
+    out.clip_position = position_world_to_clip(out.world_position.xyz);
+    out.world_normal = normal;
+    out.uv = vec2(f32(uv & 63u), f32((uv >> 6u) & 63u));
+    out.layer = (uv >> 12u) & 255u;
+    out.light = vec2(f32((uv >> 20u) & 15u), f32((uv >> 24u) & 15u)) / 15.0;
+    // each solid neighbour darkens the corner
+    out.ao = 1.0 - f32(ao) * 0.2;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput, @builtin(front_facing) is_front: bool) -> @location(0) vec4<f32> {
+    let texel = textureSample(block_textures, block_sampler, in.uv, in.layer);
+    // cutout: holes in leaves and the like
+    if texel.a < 0.1 {
+        discard;
+    }
+
+    var pbr_input = pbr_input_new();
+    pbr_input.material.base_color = vec4(texel.rgb * in.ao, texel.a);
+    pbr_input.material.reflectance = vec3(material.reflectance);
+    pbr_input.material.perceptual_roughness = material.perceptual_roughness;
+    pbr_input.material.metallic = material.metallic;
+    pbr_input.frag_coord = in.clip_position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = in.world_normal;
+    pbr_input.N = in.world_normal;
+    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
+
+    // the sun and ambient light only reach where sky light does, block light adds its own
+    let sky_light = max(in.light.x * in.light.x, MIN_LIGHT);
+    let block_light = in.light.y * in.light.y;
+    let lit = apply_pbr_lighting(pbr_input);
+    // the alpha only blends in the transparent pass, see GlobalChunkTransparentMaterial
+    let color = vec4(
+        lit.rgb * sky_light + pbr_input.material.base_color.rgb * block_light,
+        lit.a,
+    );
+    return main_pass_post_lighting_processing(pbr_input, color);
+}
@@ -0,0 +1,62 @@
+#import bevy_pbr::{
+    mesh_functions::{get_world_from_local, mesh_position_local_to_world},
+    view_transformations::position_world_to_clip,
+}
+
+// depth only, used for shadows and the depth prepass
+// the vertex layout is the one of chunk.wgsl
+
+// see ChunkMaterial
+struct ChunkMaterial {
+    reflectance: f32,
+    perceptual_roughness: f32,
+    metallic: f32,
+    origin_offset: f32,
+};
+
+@group(2) @binding(0) var<uniform> material: ChunkMaterial;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) voxel: vec2<u32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+};
+
+fn face_normal(index: u32) -> vec3<f32> {
+    switch index {
+        case 0u: { return vec3(-1.0, 0.0, 0.0); }
+        case 1u: { return vec3(1.0, 0.0, 0.0); }
+        case 2u: { return vec3(0.0, -1.0, 0.0); }
+        case 3u: { return vec3(0.0, 1.0, 0.0); }
+        case 4u: { return vec3(0.0, 0.0, -1.0); }
+        default: { return vec3(0.0, 0.0, 1.0); }
+    }
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let packed = vertex.voxel.x;
+    let position = vec3<f32>(
+        f32(packed & 63u),
+        f32((packed >> 6u) & 63u),
+        f32((packed >> 12u) & 63u),
+    ) + vec3(material.origin_offset);
+
+    let world_from_local = get_world_from_local(vertex.instance_index);
+    let world_position = mesh_position_local_to_world(world_from_local, vec4(position, 1.0));
+    var out: VertexOutput;
+    out.clip_position = position_world_to_clip(world_position.xyz);
+    out.world_normal = face_normal((packed >> 21u) & 7u);
+    return out;
+}
+
+#ifdef NORMAL_PREPASS
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(in.world_normal * 0.5 + vec3(0.5), 1.0);
+}
+#endif
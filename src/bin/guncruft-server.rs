@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use bevy::{
+    app::{ScheduleRunnerPlugin, TerminalCtrlCHandlerPlugin},
+    log::LogPlugin,
+    prelude::*,
+};
+use guncruft::{
+    environment::{
+        plugin::EnvironmentPlugin,
+        scanner::{RenderDistance, Scanner, ScannerPlugin},
+        utils::ASSETS_DIR,
+    },
+    network::server::ServerPlugin,
+};
+
+///! world updates per second
+const TICK_RATE: f64 = 60.0;
+
+///! simulates, generates and saves the world without a window or a GPU
+fn main() {
+    App::new()
+        .add_plugins(
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / TICK_RATE,
+            ))),
+        )
+        .add_plugins((
+            LogPlugin::default(),
+            TransformPlugin,
+            AssetPlugin {
+                file_path: ASSETS_DIR.to_string(),
+                ..default()
+            },
+            // ctrl-c exits the app, so the world is saved
+            TerminalCtrlCHandlerPlugin,
+        ))
+        .add_plugins(EnvironmentPlugin)
+        .add_plugins(ScannerPlugin)
+        .add_plugins(ServerPlugin::default())
+        .add_systems(Startup, keep_spawn_loaded)
+        .run();
+}
+
+///! the spawn area stays loaded while nobody is around
+fn keep_spawn_loaded(mut commands: Commands, render_distance: Res<RenderDistance>) {
+    commands.spawn((Transform::default(), Scanner::data_only(render_distance.0)));
+}
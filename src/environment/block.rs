@@ -3,21 +3,23 @@ pub struct BlockData {
     pub block_type: BlockType,
 }
 
-#[repr(u32)]
-#[derive(Eq, PartialEq, Default, Copy, Clone, Debug)]
-pub enum BlockType {
-    #[default]
-    Air,
-    Grass,
-    Dirt,
-}
+///! numeric id of a block, its characteristics live in the BlockRegistry
+#[derive(Eq, PartialEq, Hash, Default, Copy, Clone, Debug)]
+pub struct BlockType(pub u16);
 
 impl BlockType {
-    pub fn is_solid(&self) -> bool {
-        match self {
-            BlockType::Air => false,
-            BlockType::Grass => true,
-            BlockType::Dirt => true,
-        }
+    // built-in blocks, generators rely on these ids being present in the block definition file
+    pub const AIR: BlockType = BlockType(0);
+    pub const GRASS: BlockType = BlockType(1);
+    pub const DIRT: BlockType = BlockType(2);
+
+    #[inline]
+    pub fn id(&self) -> u16 {
+        self.0
+    }
+
+    #[inline]
+    pub fn is_air(&self) -> bool {
+        *self == BlockType::AIR
     }
 }
@@ -0,0 +1,352 @@
+use std::sync::Arc;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use super::{block::BlockType, engine::Engine, face_direction::FaceDir, light::MAX_LIGHT};
+
+///! the block definition file, relative to the assets folder
+pub const BLOCK_DEFINITIONS_PATH: &str = "blocks.ron";
+
+///! block ids are packed into 7 bits of the chunk vertex
+pub const MAX_BLOCK_TYPES: usize = 128;
+
+///! texture layers are packed into 8 bits of the chunk vertex, also the default wgpu limit
+pub const MAX_TEXTURE_LAYERS: usize = 256;
+
+///! everything the engine needs to know about a cube
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockDefinition {
+    pub id: u16,
+    pub name: String,
+    ///! collides with the player and stops rays
+    #[serde(default = "default_true")]
+    pub solid: bool,
+    ///! lets light and sight through, drawn blended without hiding its neighbours' faces
+    ///! texture pixels with a low alpha are cut out
+    #[serde(default)]
+    pub transparent: bool,
+    ///! linear rgba tint
+    #[serde(default = "default_color")]
+    pub color: [f32; 4],
+    ///! images of the faces, untextured faces are filled with color
+    #[serde(default)]
+    pub textures: BlockTextures,
+    ///! seconds to break the block by hand, negative is unbreakable
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+    ///! light level emitted, from 0 to 15
+    #[serde(default)]
+    pub light_emission: u8,
+}
+
+///! image paths relative to the assets folder
+///! top, side and bottom fall back to all
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BlockTextures {
+    #[serde(default)]
+    pub all: Option<String>,
+    #[serde(default)]
+    pub top: Option<String>,
+    #[serde(default)]
+    pub side: Option<String>,
+    #[serde(default)]
+    pub bottom: Option<String>,
+}
+
+impl BlockTextures {
+    pub fn face(&self, face: FaceDir) -> Option<&str> {
+        let texture = match face {
+            FaceDir::Up => &self.top,
+            FaceDir::Down => &self.bottom,
+            _ => &self.side,
+        };
+        texture.as_ref().or(self.all.as_ref()).map(String::as_str)
+    }
+}
+
+///! what a layer of the block texture array is filled with
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureLayer {
+    Image(String),
+    ///! linear rgba
+    Color([f32; 4]),
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_color() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+impl BlockDefinition {
+    fn air() -> Self {
+        Self {
+            id: BlockType::AIR.id(),
+            name: "air".into(),
+            solid: false,
+            transparent: true,
+            color: [0.0; 4],
+            textures: BlockTextures::default(),
+            hardness: 0.0,
+            light_emission: 0,
+        }
+    }
+}
+
+///! content of a block definition file
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct BlockDefinitions {
+    pub blocks: Vec<BlockDefinition>,
+}
+
+#[derive(Default)]
+pub struct BlockDefinitionsLoader;
+
+impl AssetLoader for BlockDefinitionsLoader {
+    type Asset = BlockDefinitions;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes::<BlockDefinitions>(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["blocks.ron"]
+    }
+}
+
+///! block definitions indexed by block id
+///! cheap to clone, so meshing tasks can take their own copy
+#[derive(Resource, Clone)]
+pub struct BlockRegistry {
+    blocks: Arc<Vec<Option<BlockDefinition>>>,
+    ///! texture array layer of the top, side and bottom faces, indexed by block id
+    face_layers: Arc<Vec<[u32; 3]>>,
+    ///! content of each texture array layer, identical faces share a layer
+    texture_layers: Arc<Vec<TextureLayer>>,
+}
+
+impl Default for BlockRegistry {
+    ///! the built-in blocks, used until the block definition file is loaded
+    fn default() -> Self {
+        let solid = |id: BlockType, name: &str, color: [f32; 4]| BlockDefinition {
+            id: id.id(),
+            name: name.into(),
+            solid: true,
+            transparent: false,
+            color,
+            textures: BlockTextures::default(),
+            hardness: 1.0,
+            light_emission: 0,
+        };
+        Self::from_definitions(vec![
+            BlockDefinition::air(),
+            solid(BlockType::GRASS, "grass", [0.3, 0.6, 0.2, 1.0]),
+            solid(BlockType::DIRT, "dirt", [0.45, 0.3, 0.2, 1.0]),
+        ])
+    }
+}
+
+impl BlockRegistry {
+    pub fn from_definitions(definitions: Vec<BlockDefinition>) -> Self {
+        let mut blocks: Vec<Option<BlockDefinition>> = vec![];
+        for definition in definitions {
+            let id = definition.id as usize;
+            if id >= MAX_BLOCK_TYPES {
+                warn!(
+                    "block {} has id {id}, ids above {} can't be rendered",
+                    definition.name,
+                    MAX_BLOCK_TYPES - 1
+                );
+                continue;
+            }
+            if blocks.len() <= id {
+                blocks.resize(id + 1, None);
+            }
+            if let Some(existing) = &blocks[id] {
+                warn!(
+                    "block {} reuses id {id} of block {}",
+                    definition.name, existing.name
+                );
+            }
+            blocks[id] = Some(definition);
+        }
+
+        // air is always id 0, whatever the file says
+        if blocks.is_empty() {
+            blocks.push(None);
+        }
+        blocks[BlockType::AIR.id() as usize] = Some(BlockDefinition::air());
+
+        let mut texture_layers: Vec<TextureLayer> = vec![];
+        let mut face_layers = vec![[0; 3]; blocks.len()];
+        for definition in blocks.iter().flatten() {
+            if definition.id == BlockType::AIR.id() {
+                continue;
+            }
+            for (i, face) in [FaceDir::Up, FaceDir::Left, FaceDir::Down]
+                .into_iter()
+                .enumerate()
+            {
+                let layer = match definition.textures.face(face) {
+                    Some(path) => TextureLayer::Image(path.to_string()),
+                    None => TextureLayer::Color(definition.color),
+                };
+                let index = match texture_layers.iter().position(|l| *l == layer) {
+                    Some(index) => index,
+                    None if texture_layers.len() < MAX_TEXTURE_LAYERS => {
+                        texture_layers.push(layer);
+                        texture_layers.len() - 1
+                    }
+                    None => {
+                        warn!(
+                            "block {} needs more than {MAX_TEXTURE_LAYERS} texture layers",
+                            definition.name
+                        );
+                        0
+                    }
+                };
+                face_layers[definition.id as usize][i] = index as u32;
+            }
+        }
+
+        Self {
+            blocks: Arc::new(blocks),
+            face_layers: Arc::new(face_layers),
+            texture_layers: Arc::new(texture_layers),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, block_type: BlockType) -> Option<&BlockDefinition> {
+        self.blocks.get(block_type.id() as usize)?.as_ref()
+    }
+
+    ///! unknown blocks are not solid
+    #[inline]
+    pub fn is_solid(&self, block_type: BlockType) -> bool {
+        self.get(block_type).is_some_and(|b| b.solid)
+    }
+
+    ///! unknown blocks are transparent
+    #[inline]
+    pub fn is_transparent(&self, block_type: BlockType) -> bool {
+        self.get(block_type).is_none_or(|b| b.transparent)
+    }
+
+    ///! light level the block emits, unknown blocks are dark
+    #[inline]
+    pub fn light_emission(&self, block_type: BlockType) -> u8 {
+        self.get(block_type)
+            .map_or(0, |b| b.light_emission.min(MAX_LIGHT))
+    }
+
+    ///! solid and not transparent, hides the faces of its neighbours
+    #[inline]
+    pub fn is_opaque(&self, block_type: BlockType) -> bool {
+        self.get(block_type)
+            .is_some_and(|b| b.solid && !b.transparent)
+    }
+
+    ///! drawn in the transparent pass: any known block but air that isn't opaque
+    #[inline]
+    pub fn is_translucent(&self, block_type: BlockType) -> bool {
+        !block_type.is_air()
+            && self
+                .get(block_type)
+                .is_some_and(|b| !b.solid || b.transparent)
+    }
+
+    ///! texture array layer of a block face
+    #[inline]
+    pub fn face_layer(&self, block_type: BlockType, face: FaceDir) -> u32 {
+        let Some(layers) = self.face_layers.get(block_type.id() as usize) else {
+            return 0;
+        };
+        match face {
+            FaceDir::Up => layers[0],
+            FaceDir::Down => layers[2],
+            _ => layers[1],
+        }
+    }
+
+    pub fn texture_layers(&self) -> &[TextureLayer] {
+        &self.texture_layers
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<BlockType> {
+        self.iter()
+            .find(|b| b.name == name)
+            .map(|b| BlockType(b.id))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BlockDefinition> {
+        self.blocks.iter().flatten()
+    }
+}
+
+#[derive(Resource)]
+pub struct BlockDefinitionsHandle(pub Handle<BlockDefinitions>);
+
+pub fn load_block_definitions(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(BlockDefinitionsHandle(
+        asset_server.load(BLOCK_DEFINITIONS_PATH),
+    ));
+}
+
+///! rebuild the registry whenever the block definition file is (re)loaded,
+///! and relight and remesh every chunk so the new definitions show up
+pub fn apply_block_definitions(
+    mut events: EventReader<AssetEvent<BlockDefinitions>>,
+    definitions: Res<Assets<BlockDefinitions>>,
+    handle: Res<BlockDefinitionsHandle>,
+    mut registry: ResMut<BlockRegistry>,
+    mut voxel_engine: ResMut<Engine>,
+) {
+    for event in events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event
+        else {
+            continue;
+        };
+        if *id != handle.0.id() {
+            continue;
+        }
+        let Some(file) = definitions.get(*id) else {
+            continue;
+        };
+
+        *registry = BlockRegistry::from_definitions(file.blocks.clone());
+        info!("loaded {} block definitions", registry.iter().count());
+
+        // emission and transparency may have changed
+        voxel_engine.relight_all();
+        let Engine {
+            chunk_entities,
+            load_mesh_queue,
+            ..
+        } = voxel_engine.as_mut();
+        for chunk_pos in chunk_entities.keys() {
+            if !load_mesh_queue.contains(chunk_pos) {
+                load_mesh_queue.push(*chunk_pos);
+            }
+        }
+    }
+}
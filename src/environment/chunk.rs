@@ -6,7 +6,9 @@ use bevy::{
 };
 
 use super::{
-    block::BlockData,
+    block::{BlockData, BlockType},
+    light::ChunkLight,
+    palette::PackedIndices,
     quad::Direction,
     utils::{index_to_ivec3_bounds, vec3_to_index},
 };
@@ -20,53 +22,178 @@ pub const CHUNK_SIZE2: usize = CHUNK_SIZE * CHUNK_SIZE;
 pub const CHUNK_SIZE2_I32: i32 = CHUNK_SIZE2 as i32;
 pub const CHUNK_SIZE3: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
 
+///! voxels of a chunk, stored as a palette of distinct blocks and a bit packed palette index per voxel
+///! a chunk with a single palette entry is filled with it and stores no indices
 #[derive(Clone)]
 pub struct ChunkData {
-    pub voxels: Vec<BlockData>,
+    palette: Vec<BlockData>,
+    indices: PackedIndices,
 }
 
 impl ChunkData {
+    ///! build the chunk at chunk_pos by asking block_at for every voxel's world position
+    pub fn from_fn(chunk_pos: IVec3, block_at: impl Fn(IVec3) -> BlockType) -> Self {
+        let origin = chunk_pos * CHUNK_SIZE_I32;
+        let mut palette: Vec<BlockData> = vec![];
+        let mut voxels = Vec::with_capacity(CHUNK_SIZE3);
+        for i in 0..CHUNK_SIZE3 {
+            let pos = index_to_ivec3_bounds(i as i32, CHUNK_SIZE_I32);
+            let block_type = block_at(origin + pos);
+            let palette_index = match palette.iter().position(|b| b.block_type == block_type) {
+                Some(palette_index) => palette_index,
+                None => {
+                    palette.push(BlockData { block_type });
+                    palette.len() - 1
+                }
+            };
+            voxels.push(palette_index);
+        }
+        Self::from_palette(palette, &voxels)
+    }
+
+    ///! a chunk where every voxel is block_type, stored as a single palette entry
+    pub fn filled(block_type: BlockType) -> Self {
+        Self {
+            palette: vec![BlockData { block_type }],
+            indices: PackedIndices::default(),
+        }
+    }
+
+    ///! build a chunk from a full voxel vec
+    ///! collapses to the single voxel form if all voxels are the same
+    pub fn from_voxels(voxels: Vec<BlockData>) -> Self {
+        Self::from_fn(IVec3::ZERO, |pos| {
+            voxels[vec3_to_index(pos, CHUNK_SIZE_I32)].block_type
+        })
+    }
+
+    ///! build a chunk from a palette and a palette index per voxel
+    pub fn from_palette(palette: Vec<BlockData>, voxels: &[usize]) -> Self {
+        if palette.len() == 1 {
+            return Self::filled(palette[0].block_type);
+        }
+        let mut indices = PackedIndices::new(CHUNK_SIZE3, PackedIndices::bits_for(palette.len()));
+        for (i, palette_index) in voxels.iter().enumerate() {
+            indices.set(i, *palette_index);
+        }
+        Self { palette, indices }
+    }
+
     #[inline]
     pub fn get_block(&self, index: usize) -> &BlockData {
-        if self.voxels.len() == 1 {
-            &self.voxels[0]
-        } else {
-            &self.voxels[index]
-        }
+        &self.palette[self.indices.get(index)]
     }
 
     // returns the block type if all voxels are the same
     #[inline]
     pub fn get_block_if_filled(&self) -> Option<&BlockData> {
-        if self.voxels.len() == 1 {
-            Some(&self.voxels[0])
+        if self.palette.len() == 1 {
+            Some(&self.palette[0])
         } else {
             None
         }
     }
+
+    ///! replace the voxel at index, growing the palette if the block is new to this chunk
+    pub fn set_block(&mut self, index: usize, block: BlockData) {
+        let palette_index = match self
+            .palette
+            .iter()
+            .position(|b| b.block_type == block.block_type)
+        {
+            Some(palette_index) => palette_index,
+            None => {
+                if self.palette.len() > self.indices.max_value() {
+                    // drop entries no voxel points to anymore before widening the indices
+                    self.compact();
+                }
+                self.palette.push(block);
+                let bits = PackedIndices::bits_for(self.palette.len());
+                if bits != self.indices.bits() {
+                    self.indices = self.indices.repacked(CHUNK_SIZE3, bits);
+                }
+                self.palette.len() - 1
+            }
+        };
+        self.indices.set(index, palette_index);
+    }
+
+    ///! distinct blocks of the chunk, voxel indices point into it
+    pub fn palette(&self) -> &[BlockData] {
+        &self.palette
+    }
+
+    ///! palette index of every voxel, empty for a filled chunk
+    pub fn indices(&self) -> &PackedIndices {
+        &self.indices
+    }
+
+    ///! rebuild a chunk from its palette and indices, None if they don't fit together
+    pub fn from_packed(palette: Vec<BlockData>, indices: PackedIndices) -> Option<Self> {
+        match palette.len() {
+            0 => return None,
+            1 => return Some(Self::filled(palette[0].block_type)),
+            _ => {}
+        }
+        if indices.bits() < PackedIndices::bits_for(palette.len()) {
+            return None;
+        }
+        if (0..CHUNK_SIZE3).any(|i| indices.get(i) >= palette.len()) {
+            return None;
+        }
+        Some(Self { palette, indices })
+    }
+
+    ///! rebuild the palette with only the blocks still in use
+    ///! collapses to the single voxel form if all voxels ended up the same
+    pub fn compact(&mut self) {
+        if self.palette.len() == 1 {
+            return;
+        }
+        let mut remap = vec![usize::MAX; self.palette.len()];
+        let mut palette = vec![];
+        let mut voxels = Vec::with_capacity(CHUNK_SIZE3);
+        for i in 0..CHUNK_SIZE3 {
+            let old = self.indices.get(i);
+            if remap[old] == usize::MAX {
+                remap[old] = palette.len();
+                palette.push(self.palette[old]);
+            }
+            voxels.push(remap[old]);
+        }
+        *self = Self::from_palette(palette, &voxels);
+    }
+
+    ///! heap memory used by the voxels
+    pub fn byte_size(&self) -> usize {
+        self.palette.len() * size_of::<BlockData>() + self.indices.byte_size()
+    }
 }
 
 // pointers to chunk data, a middle one with all their neighbours
 #[derive(Clone)]
 pub struct ChunksRefs {
     pub chunks: Vec<Arc<ChunkData>>,
+    ///! light of the same chunks
+    pub lights: Vec<Arc<ChunkLight>>,
 }
 
 impl ChunksRefs {
     ///! construct a ChunkRefs at middle_chunk position
-    ///! safety: panics if ChunkData doesn't exist in input world_data
+    ///! None if any of the chunks isn't loaded or lit yet
     pub fn try_new(
         world_data: &HashMap<IVec3, Arc<ChunkData>>,
+        world_light: &HashMap<IVec3, Arc<ChunkLight>>,
         middle_chunk: IVec3,
     ) -> Option<Self> {
         let mut chunks = vec![];
+        let mut lights = vec![];
         for i in 0..3 * 3 * 3 {
             let offset = index_to_ivec3_bounds(i, 3) + IVec3::splat(-1);
-            chunks.push(Arc::clone(
-                world_data.get(&(middle_chunk + offset)).unwrap(),
-            ))
+            chunks.push(Arc::clone(world_data.get(&(middle_chunk + offset))?));
+            lights.push(Arc::clone(world_light.get(&(middle_chunk + offset))?));
         }
-        Some(Self { chunks })
+        Some(Self { chunks, lights })
     }
 
     // returns if all the voxels are the same
@@ -92,9 +219,10 @@ impl ChunksRefs {
         true
     }
 
-    ///! helper function to get block data that may exceed the bounds of the middle chunk
+    ///! index of the chunk holding pos, and of pos inside it
     ///! input position is local pos to middle chunk
-    pub fn get_block(&self, pos: IVec3) -> &BlockData {
+    #[inline]
+    fn locate(pos: IVec3) -> (usize, usize) {
         let x = (pos.x + 32) as u32;
         let y = (pos.y + 32) as u32;
         let z = (pos.z + 32) as u32;
@@ -103,9 +231,21 @@ impl ChunksRefs {
         let (z_chunk, z) = ((z / 32) as i32, (z % 32) as i32);
 
         let chunk_index = vec3_to_index(IVec3::new(x_chunk, y_chunk, z_chunk), 3);
-        let chunk_data = &self.chunks[chunk_index];
         let i = vec3_to_index(IVec3::new(x, y, z), 32);
-        chunk_data.get_block(i)
+        (chunk_index, i)
+    }
+
+    ///! helper function to get block data that may exceed the bounds of the middle chunk
+    ///! input position is local pos to middle chunk
+    pub fn get_block(&self, pos: IVec3) -> &BlockData {
+        let (chunk_index, i) = Self::locate(pos);
+        self.chunks[chunk_index].get_block(i)
+    }
+
+    ///! packed sky and block light, may exceed the bounds of the middle chunk like get_block
+    pub fn get_light(&self, pos: IVec3) -> u8 {
+        let (chunk_index, i) = Self::locate(pos);
+        self.lights[chunk_index].get(i)
     }
 
     ///! helper function to get voxels
@@ -147,3 +287,74 @@ impl ChunksRefs {
         (first, second)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: BlockType = BlockType(3);
+
+    fn block(block_type: BlockType) -> BlockData {
+        BlockData { block_type }
+    }
+
+    #[test]
+    fn setting_a_filled_chunk_to_its_own_block_keeps_it_filled() {
+        let mut chunk = ChunkData::filled(BlockType::DIRT);
+        chunk.set_block(5, block(BlockType::DIRT));
+        assert_eq!(chunk.palette().len(), 1);
+        assert_eq!(chunk.indices().bits(), 0);
+        assert_eq!(chunk.get_block(5).block_type, BlockType::DIRT);
+    }
+
+    #[test]
+    fn new_blocks_widen_the_indices() {
+        let mut chunk = ChunkData::filled(BlockType::AIR);
+        chunk.set_block(1, block(BlockType::GRASS));
+        assert_eq!(chunk.indices().bits(), 1);
+        chunk.set_block(2, block(BlockType::DIRT));
+        chunk.set_block(3, block(STONE));
+        assert_eq!(chunk.indices().bits(), 2);
+        chunk.set_block(4, block(BlockType(4)));
+        assert_eq!(chunk.indices().bits(), 4);
+
+        let expected = [BlockType::AIR, BlockType::GRASS, BlockType::DIRT, STONE];
+        for (i, block_type) in expected.into_iter().enumerate() {
+            assert_eq!(chunk.get_block(i).block_type, block_type);
+        }
+        assert_eq!(chunk.get_block(4).block_type, BlockType(4));
+        assert_eq!(chunk.get_block(CHUNK_SIZE3 - 1).block_type, BlockType::AIR);
+    }
+
+    #[test]
+    fn compact_drops_unused_blocks_and_collapses_filled_chunks() {
+        let mut chunk = ChunkData::filled(BlockType::AIR);
+        chunk.set_block(0, block(BlockType::GRASS));
+        chunk.set_block(1, block(BlockType::DIRT));
+        chunk.set_block(0, block(BlockType::AIR));
+        chunk.compact();
+        assert_eq!(chunk.palette().len(), 2);
+        assert_eq!(chunk.get_block(0).block_type, BlockType::AIR);
+        assert_eq!(chunk.get_block(1).block_type, BlockType::DIRT);
+
+        chunk.set_block(1, block(BlockType::AIR));
+        chunk.compact();
+        assert_eq!(
+            chunk.get_block_if_filled().map(|b| b.block_type),
+            Some(BlockType::AIR)
+        );
+        assert_eq!(chunk.indices().bits(), 0);
+    }
+
+    #[test]
+    fn a_full_palette_is_compacted_before_widening() {
+        let mut chunk = ChunkData::filled(BlockType::AIR);
+        chunk.set_block(0, block(BlockType::GRASS));
+        // grass is gone, so dirt takes its place without a wider index
+        chunk.set_block(0, block(BlockType::AIR));
+        chunk.set_block(1, block(BlockType::DIRT));
+        assert_eq!(chunk.indices().bits(), 1);
+        assert_eq!(chunk.get_block(0).block_type, BlockType::AIR);
+        assert_eq!(chunk.get_block(1).block_type, BlockType::DIRT);
+    }
+}
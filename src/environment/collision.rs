@@ -0,0 +1,81 @@
+use bevy::prelude::*;
+
+use super::{block_registry::BlockRegistry, engine::Engine};
+
+///! boxes are shrunk by this much when looking for overlapping voxels,
+///! so a box resting on a face doesn't count as overlapping the voxel behind it
+const SKIN: f32 = 1e-4;
+
+impl Engine {
+    ///! unloaded chunks count as solid, so nothing falls out of the loaded world
+    pub fn is_solid_at(&self, registry: &BlockRegistry, world_pos: IVec3) -> bool {
+        self.get_block(world_pos)
+            .is_none_or(|block| registry.is_solid(block.block_type))
+    }
+
+    ///! how far the box (min, max) can travel by delta along axis (0 = x, 1 = y, 2 = z)
+    ///! before touching a solid voxel
+    ///! voxels the box already overlaps are ignored, so it can always move out of them
+    pub fn sweep_aabb_axis(
+        &self,
+        registry: &BlockRegistry,
+        min: Vec3,
+        max: Vec3,
+        axis: usize,
+        delta: f32,
+    ) -> f32 {
+        if delta == 0.0 {
+            return 0.0;
+        }
+
+        // voxels overlapped on the two other axes
+        let mut lo = (min + SKIN).floor().as_ivec3();
+        let mut hi = (max - SKIN).floor().as_ivec3();
+
+        // voxels between the leading face and where it ends up
+        if delta > 0.0 {
+            lo[axis] = (max[axis] - SKIN).ceil() as i32;
+            hi[axis] = (max[axis] + delta).ceil() as i32 - 1;
+        } else {
+            lo[axis] = (min[axis] + delta).floor() as i32;
+            hi[axis] = (min[axis] + SKIN).floor() as i32 - 1;
+        }
+
+        let mut allowed = delta;
+        for z in lo.z..=hi.z {
+            for y in lo.y..=hi.y {
+                for x in lo.x..=hi.x {
+                    let voxel = IVec3::new(x, y, z);
+                    if !self.is_solid_at(registry, voxel) {
+                        continue;
+                    }
+                    let v = voxel[axis] as f32;
+                    allowed = if delta > 0.0 {
+                        allowed.min(v - max[axis])
+                    } else {
+                        allowed.max(v + 1.0 - min[axis])
+                    };
+                }
+            }
+        }
+
+        // never push the box backwards
+        if delta > 0.0 {
+            allowed.max(0.0)
+        } else {
+            allowed.min(0.0)
+        }
+    }
+
+    ///! move the box (min, max) by motion, sliding along solid voxels one axis at a time,
+    ///! vertical first
+    ///! returns the motion that was actually applied
+    pub fn move_aabb(&self, registry: &BlockRegistry, min: Vec3, max: Vec3, motion: Vec3) -> Vec3 {
+        let mut applied = Vec3::ZERO;
+        for axis in [1, 0, 2] {
+            applied[axis] =
+                self.sweep_aabb_axis(registry, min + applied, max + applied, axis, motion[axis]);
+        }
+        applied
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    math::{IVec3, ivec3},
+    platform::collections::HashMap,
+    prelude::*,
+};
+
+use super::{
+    block_registry::BlockRegistry,
+    chunk::{CHUNK_SIZE_I32, CHUNK_SIZE3, ChunkData},
+    engine::Engine,
+    utils::{index_to_ivec3_bounds, vec3_to_index, world_to_chunk},
+};
+
+///! the faces of a chunk, indexed like FaceConnections
+pub const CHUNK_FACES: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Y,
+    IVec3::Y,
+    IVec3::NEG_Z,
+    IVec3::Z,
+];
+
+///! index in CHUNK_FACES of the face on the other side
+#[inline]
+fn opposite(face: usize) -> usize {
+    face ^ 1
+}
+
+///! which faces of a chunk can see each other through its see-through voxels
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FaceConnections(u64);
+
+impl FaceConnections {
+    pub const ALL: FaceConnections = FaceConnections((1 << 36) - 1);
+    pub const NONE: FaceConnections = FaceConnections(0);
+
+    ///! flood fill the voxels that aren't opaque, faces touched by the same region are connected
+    pub fn from_chunk(chunk: &ChunkData, registry: &BlockRegistry) -> Self {
+        if let Some(block) = chunk.get_block_if_filled() {
+            return match registry.is_opaque(block.block_type) {
+                true => Self::NONE,
+                false => Self::ALL,
+            };
+        }
+
+        let mut connections = Self::NONE;
+        let mut visited = vec![false; CHUNK_SIZE3];
+        let mut queue = VecDeque::new();
+        for start in 0..CHUNK_SIZE3 {
+            if visited[start] || registry.is_opaque(chunk.get_block(start).block_type) {
+                continue;
+            }
+            visited[start] = true;
+            queue.push_back(start);
+            // faces reached by this region of connected voxels
+            let mut faces = 0u8;
+            while let Some(i) = queue.pop_front() {
+                let pos = index_to_ivec3_bounds(i as i32, CHUNK_SIZE_I32);
+                for (face, dir) in CHUNK_FACES.iter().enumerate() {
+                    let next = pos + *dir;
+                    if next.cmplt(IVec3::ZERO).any()
+                        || next.cmpge(IVec3::splat(CHUNK_SIZE_I32)).any()
+                    {
+                        faces |= 1 << face;
+                        continue;
+                    }
+                    let n = vec3_to_index(next, CHUNK_SIZE_I32);
+                    if visited[n] || registry.is_opaque(chunk.get_block(n).block_type) {
+                        continue;
+                    }
+                    visited[n] = true;
+                    queue.push_back(n);
+                }
+            }
+            for a in 0..6 {
+                for b in 0..6 {
+                    if faces & (1 << a) != 0 && faces & (1 << b) != 0 {
+                        connections.0 |= 1 << (a * 6 + b);
+                    }
+                }
+            }
+            if connections == Self::ALL {
+                break;
+            }
+        }
+        connections
+    }
+
+    #[inline]
+    pub fn connects(&self, a: usize, b: usize) -> bool {
+        self.0 & (1 << (a * 6 + b)) != 0
+    }
+}
+
+///! hides chunks the camera can't see through the see-through voxels of the chunks in between
+///!
+///! walks the chunks from the camera's one, going from a chunk to its neighbour through a face
+///! only if the face it was entered from connects to it, and never back against a direction
+///! already taken. chunks that aren't meshed yet are assumed to connect all their faces.
+pub fn cave_culling(
+    voxel_engine: Res<Engine>,
+    cameras: Query<(&GlobalTransform, &Camera), With<Camera3d>>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    // the world camera, cameras drawn on top of it like the view model don't look at chunks
+    let Some((camera_transform, _)) = cameras
+        .iter()
+        .find(|(_, camera)| camera.is_active && camera.order == 0)
+    else {
+        return;
+    };
+    let (camera_chunk, _) = world_to_chunk(camera_transform.translation().floor().as_ivec3());
+
+    let Engine {
+        chunk_entities,
+        chunk_connections,
+        mesh_refs,
+        ..
+    } = voxel_engine.as_ref();
+
+    // faces each chunk has been entered from
+    let mut entered: HashMap<IVec3, u8> = HashMap::new();
+    entered.insert(camera_chunk, u8::MAX);
+    // chunk, face it was entered from, directions taken to reach it
+    let mut queue = VecDeque::from([(camera_chunk, None::<usize>, 0u8)]);
+    while let Some((chunk_pos, entry, directions)) = queue.pop_front() {
+        let connections = chunk_connections
+            .get(&chunk_pos)
+            .copied()
+            .unwrap_or(FaceConnections::ALL);
+        for (face, dir) in CHUNK_FACES.iter().enumerate() {
+            if directions & (1 << opposite(face)) != 0 {
+                continue;
+            }
+            if entry.is_some_and(|entry| !connections.connects(entry, face)) {
+                continue;
+            }
+            let next = chunk_pos + *dir;
+            // past the area scanners keep meshed
+            if !mesh_refs.contains_key(&next) {
+                continue;
+            }
+            let next_entry = opposite(face);
+            let entries = entered.entry(next).or_default();
+            if *entries & (1 << next_entry) != 0 {
+                continue;
+            }
+            *entries |= 1 << next_entry;
+            queue.push_back((next, Some(next_entry), directions | 1 << face));
+        }
+    }
+
+    for (chunk_pos, entity) in chunk_entities.iter() {
+        let Ok(mut visibility) = visibilities.get_mut(*entity) else {
+            continue;
+        };
+        let visible = match entered.contains_key(chunk_pos) {
+            true => Visibility::Inherited,
+            false => Visibility::Hidden,
+        };
+        visibility.set_if_neq(visible);
+    }
+}
+
+///! corners of the box around the packed vertex positions of a chunk mesh, in blocks
+pub fn vertex_bounds(vertices: &[[u32; 2]]) -> (Vec3, Vec3) {
+    let mut min = IVec3::splat(i32::MAX);
+    let mut max = IVec3::splat(i32::MIN);
+    for [packed, _] in vertices {
+        let pos = ivec3(
+            (packed & 63) as i32,
+            ((packed >> 6) & 63) as i32,
+            ((packed >> 12) & 63) as i32,
+        );
+        min = min.min(pos);
+        max = max.max(pos);
+    }
+    (min.as_vec3(), max.as_vec3())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn chunks_behind_a_sealed_chunk_are_hidden() {
+        let mut world = World::new();
+        let mut engine = Engine::default();
+        // a row of meshed chunks, the middle one is solid rock
+        for x in 0..3 {
+            let chunk_pos = IVec3::new(x, 0, 0);
+            let entity = world.spawn(Visibility::Inherited).id();
+            engine.chunk_entities.insert(chunk_pos, entity);
+            engine.mesh_refs.insert(chunk_pos, 1);
+            engine
+                .chunk_connections
+                .insert(chunk_pos, FaceConnections::ALL);
+        }
+        engine
+            .chunk_connections
+            .insert(IVec3::X, FaceConnections::NONE);
+        world.insert_resource(engine);
+
+        let camera_pos = Vec3::splat(CHUNK_SIZE_I32 as f32 / 2.0);
+        world.spawn((
+            Camera3d::default(),
+            Camera::default(),
+            GlobalTransform::from_translation(camera_pos),
+        ));
+        // the view model camera sits right by it and must not stop the pass
+        world.spawn((
+            Camera3d::default(),
+            Camera {
+                order: 1,
+                ..default()
+            },
+            GlobalTransform::from_translation(camera_pos),
+        ));
+
+        world.run_system_once(cave_culling).unwrap();
+
+        let visibility = |world: &World, x: i32| {
+            let entity = world.resource::<Engine>().chunk_entities[&IVec3::new(x, 0, 0)];
+            *world.get::<Visibility>(entity).unwrap()
+        };
+        assert_eq!(visibility(&world, 0), Visibility::Inherited);
+        assert_eq!(visibility(&world, 1), Visibility::Inherited);
+        assert_eq!(visibility(&world, 2), Visibility::Hidden);
+    }
+}
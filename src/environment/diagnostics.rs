@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+};
+
+use super::engine::Engine;
+
+///! engine measurements, read them from the DiagnosticsStore resource
+pub struct EngineDiagnosticsPlugin;
+
+impl EngineDiagnosticsPlugin {
+    pub const LOADED_CHUNKS: DiagnosticPath = DiagnosticPath::const_new("engine/loaded_chunks");
+    pub const MESHED_CHUNKS: DiagnosticPath = DiagnosticPath::const_new("engine/meshed_chunks");
+    pub const DATA_LOAD_QUEUE: DiagnosticPath = DiagnosticPath::const_new("engine/data_load_queue");
+    pub const DATA_UNLOAD_QUEUE: DiagnosticPath =
+        DiagnosticPath::const_new("engine/data_unload_queue");
+    pub const MESH_LOAD_QUEUE: DiagnosticPath = DiagnosticPath::const_new("engine/mesh_load_queue");
+    pub const MESH_UNLOAD_QUEUE: DiagnosticPath =
+        DiagnosticPath::const_new("engine/mesh_unload_queue");
+    pub const DATA_TASKS: DiagnosticPath = DiagnosticPath::const_new("engine/data_tasks");
+    pub const MESH_TASKS: DiagnosticPath = DiagnosticPath::const_new("engine/mesh_tasks");
+    ///! average over the meshes joined each frame, only measured on frames that joined some
+    pub const MESH_BUILD_TIME: DiagnosticPath = DiagnosticPath::const_new("engine/mesh_build_time");
+    pub const VERTICES: DiagnosticPath = DiagnosticPath::const_new("engine/vertices");
+    pub const WASTED_TASKS: DiagnosticPath = DiagnosticPath::const_new("engine/wasted_tasks");
+}
+
+impl Plugin for EngineDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        for path in [
+            Self::LOADED_CHUNKS,
+            Self::MESHED_CHUNKS,
+            Self::DATA_LOAD_QUEUE,
+            Self::DATA_UNLOAD_QUEUE,
+            Self::MESH_LOAD_QUEUE,
+            Self::MESH_UNLOAD_QUEUE,
+            Self::DATA_TASKS,
+            Self::MESH_TASKS,
+            Self::VERTICES,
+            Self::WASTED_TASKS,
+        ] {
+            // counts, no point smoothing them
+            app.register_diagnostic(Diagnostic::new(path).with_smoothing_factor(0.0));
+        }
+        app.register_diagnostic(Diagnostic::new(Self::MESH_BUILD_TIME).with_suffix("ms"));
+        app.add_systems(Last, engine_diagnostics);
+    }
+}
+
+fn engine_diagnostics(mut diagnostics: Diagnostics, mut voxel_engine: ResMut<Engine>) {
+    type P = EngineDiagnosticsPlugin;
+    let engine = voxel_engine.as_ref();
+    diagnostics.add_measurement(&P::LOADED_CHUNKS, || engine.world_data.len() as f64);
+    diagnostics.add_measurement(&P::MESHED_CHUNKS, || engine.chunk_entities.len() as f64);
+    diagnostics.add_measurement(&P::DATA_LOAD_QUEUE, || engine.load_data_queue.len() as f64);
+    diagnostics.add_measurement(&P::DATA_UNLOAD_QUEUE, || {
+        engine.unload_data_queue.len() as f64
+    });
+    diagnostics.add_measurement(&P::MESH_LOAD_QUEUE, || engine.load_mesh_queue.len() as f64);
+    diagnostics.add_measurement(&P::MESH_UNLOAD_QUEUE, || {
+        engine.unload_mesh_queue.len() as f64
+    });
+    diagnostics.add_measurement(&P::DATA_TASKS, || engine.data_tasks.len() as f64);
+    diagnostics.add_measurement(&P::MESH_TASKS, || engine.mesh_tasks.len() as f64);
+    diagnostics.add_measurement(&P::VERTICES, || {
+        engine.vertex_counts.values().sum::<usize>() as f64
+    });
+    diagnostics.add_measurement(&P::WASTED_TASKS, || engine.task_metrics.wasted() as f64);
+
+    let build_times = std::mem::take(&mut voxel_engine.mesh_build_times);
+    if !build_times.is_empty() {
+        let total = build_times.iter().sum::<Duration>();
+        diagnostics.add_measurement(&P::MESH_BUILD_TIME, || {
+            total.as_secs_f64() * 1000.0 / build_times.len() as f64
+        });
+    }
+}
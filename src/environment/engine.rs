@@ -1,29 +1,85 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use bevy::{
     asset::{LoadState, RenderAssetUsages},
     math::IVec3,
-    platform::collections::{HashMap, HashSet},
+    pbr::NotShadowCaster,
+    platform::{
+        collections::{HashMap, HashSet},
+        time::Instant,
+    },
     prelude::*,
+    render::primitives::Aabb,
     tasks::{AsyncComputeTaskPool, Task, block_on, futures_lite::future},
 };
 
 use super::{
     block::{BlockData, BlockType},
-    chunk::{CHUNK_SIZE3, ChunkData, ChunksRefs},
-    mesher::{self, ChunkMesh},
-    rendering::ATTRIBUTE_VOXEL,
-    scanner::Scanner,
-    utils::{get_edging_chunk, vec3_to_index},
+    block_registry::BlockRegistry,
+    chunk::{CHUNK_SIZE_I32, ChunkData, ChunksRefs},
+    culling::{FaceConnections, vertex_bounds},
+    generator::{NoiseGenerator, WORLD_SEED, WorldGenerator},
+    light::{ChunkLight, LightPropagation, MAX_LIGHT_CHUNKS},
+    mesher::{self, ChunkMesh, ChunkMeshes},
+    region::WorldStorage,
+    rendering::{
+        ATTRIBUTE_VOXEL, ChunkMaterialWireframeMode, GlobalChunkMaterial,
+        GlobalChunkTransparentMaterial, GlobalChunkWireframeMaterial, TRANSPARENT_MESH_ORIGIN,
+        TransparentChunkMesh,
+    },
+    scanner::{ADJACENT_CHUNK_DIRECTIONS, Scanner, closest_distance_squared, scanner_chunk_pos},
+    utils::{cli_arg, get_edging_chunk, vec3_to_index},
+    voxel_world::BlockChanged,
 };
 
 pub const MAX_DATA_TASKS: usize = 64;
 pub const MAX_MESH_TASKS: usize = 32;
 
+///! where the world is saved, unless `--world <dir>` is given
+pub const DEFAULT_WORLD_DIR: &str = "world";
+
 pub struct ChunkModification(pub IVec3, pub BlockType);
 
+///! an in flight meshing task, tagged with the version of the chunk it was started from
+pub struct MeshTask {
+    pub version: u32,
+    ///! the meshes, which faces of the chunk see each other, and how long it took
+    pub task: Option<Task<(ChunkMeshes, FaceConnections, Duration)>>,
+}
+
+///! counts of background work, to see how much of it is thrown away
+#[derive(Default, Copy, Clone, Debug)]
+pub struct TaskMetrics {
+    pub data_completed: u64,
+    pub mesh_completed: u64,
+    ///! generation tasks dropped because every scanner moved away
+    pub data_cancelled: u64,
+    ///! meshing tasks dropped because every scanner moved away
+    pub mesh_cancelled: u64,
+    ///! meshing tasks replaced by a newer one for the same chunk
+    pub mesh_superseded: u64,
+    ///! finished chunk data nobody wanted anymore
+    pub data_discarded: u64,
+    ///! finished meshes built from blocks that changed since
+    pub mesh_stale: u64,
+}
+
+impl TaskMetrics {
+    ///! tasks whose work never made it into the world
+    pub fn wasted(&self) -> u64 {
+        self.data_cancelled
+            + self.mesh_cancelled
+            + self.mesh_superseded
+            + self.data_discarded
+            + self.mesh_stale
+    }
+}
+
+///! chunks closer than this to a scanner, in chunks, are meshed at full detail
+pub const LOD_FULL_DISTANCE: i32 = 3;
+
 ///! level of detail
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Lod {
     L32,
     L16,
@@ -55,46 +111,207 @@ impl Lod {
             Lod::L2 => 16,
         }
     }
+
+    ///! detail for a chunk this far from the closest scanner
+    ///! each ring is twice as wide as the previous one and halves the resolution
+    pub fn from_distance_squared(distance_squared: i32) -> Lod {
+        let full = LOD_FULL_DISTANCE * LOD_FULL_DISTANCE;
+        match distance_squared {
+            d if d <= full => Lod::L32,
+            d if d <= full * 4 => Lod::L16,
+            d if d <= full * 16 => Lod::L8,
+            d if d <= full * 64 => Lod::L4,
+            _ => Lod::L2,
+        }
+    }
 }
 
 #[derive(Resource)]
 pub struct Engine {
     pub world_data: HashMap<IVec3, Arc<ChunkData>>,
+    ///! sky and block light of the loaded chunks, chunks can only be meshed once lit
+    pub world_light: HashMap<IVec3, Arc<ChunkLight>>,
+    ///! loaded chunks waiting for their light
+    pub unlit_chunks: Vec<IVec3>,
     pub load_data_queue: Vec<IVec3>,
     pub load_mesh_queue: Vec<IVec3>,
     pub unload_data_queue: Vec<IVec3>,
     pub unload_mesh_queue: Vec<IVec3>,
     pub data_tasks: HashMap<IVec3, Option<Task<ChunkData>>>,
-    pub mesh_tasks: Vec<(IVec3, Option<Task<Option<ChunkMesh>>>)>,
+    ///! at most one per chunk, a newer task replaces the older one
+    pub mesh_tasks: HashMap<IVec3, MeshTask>,
     pub chunk_entities: HashMap<IVec3, Entity>,
-    pub lod: Lod,
+    ///! visibility between the faces of the meshed chunks, for cave culling
+    pub chunk_connections: HashMap<IVec3, FaceConnections>,
+    ///! bumped whenever the blocks a chunk mesh is built from change
+    pub mesh_versions: HashMap<IVec3, u32>,
+    pub task_metrics: TaskMetrics,
+    ///! build times of the meshes joined since the diagnostics last read them
+    pub mesh_build_times: Vec<Duration>,
+    ///! vertices in each chunk's mesh
+    pub vertex_counts: HashMap<IVec3, usize>,
+    ///! the detail each chunk was last meshed at
+    pub chunk_lods: HashMap<IVec3, Lod>,
     pub chunk_modifications: HashMap<IVec3, Vec<ChunkModification>>,
+    ///! the generator new chunks are built with
+    pub generator: Arc<dyn WorldGenerator>,
+    ///! region files chunks are loaded from and saved to
+    pub storage: Arc<WorldStorage>,
+    ///! chunks modified since they were loaded
+    pub dirty_chunks: HashSet<IVec3>,
+    ///! how many scanners want each chunk's data loaded
+    pub data_refs: HashMap<IVec3, u32>,
+    ///! how many scanners want each chunk meshed
+    pub mesh_refs: HashMap<IVec3, u32>,
+    ///! chunks come from a server, nothing is generated or saved here
+    pub streamed: bool,
 }
 
 impl Default for Engine {
     fn default() -> Engine {
         return Engine {
             world_data: HashMap::new(),
+            world_light: HashMap::new(),
+            unlit_chunks: Vec::new(),
             load_data_queue: Vec::new(),
             load_mesh_queue: Vec::new(),
             unload_data_queue: Vec::new(),
             unload_mesh_queue: Vec::new(),
             data_tasks: HashMap::new(),
-            mesh_tasks: Vec::new(),
+            mesh_tasks: HashMap::new(),
             chunk_entities: HashMap::new(),
-            lod: Lod::L32,
+            chunk_connections: HashMap::new(),
+            chunk_lods: HashMap::new(),
+            mesh_versions: HashMap::new(),
+            task_metrics: TaskMetrics::default(),
+            mesh_build_times: Vec::new(),
+            vertex_counts: HashMap::new(),
             chunk_modifications: HashMap::new(),
+            generator: Arc::new(NoiseGenerator::new(WORLD_SEED)),
+            storage: Arc::new(WorldStorage::new(DEFAULT_WORLD_DIR)),
+            dirty_chunks: HashSet::new(),
+            data_refs: HashMap::new(),
+            mesh_refs: HashMap::new(),
+            streamed: false,
         };
     }
 }
 
 impl Engine {
+    ///! write a dirty chunk to its region file
+    pub fn save_chunk(&mut self, chunk_pos: IVec3) {
+        if !self.dirty_chunks.remove(&chunk_pos) || self.streamed {
+            return;
+        }
+        let Some(chunk_data) = self.world_data.get(&chunk_pos) else {
+            return;
+        };
+        if let Err(e) = self.storage.save_chunk(chunk_pos, chunk_data) {
+            error!("failed to save chunk {chunk_pos}: {e}");
+        }
+    }
+
+    ///! put a chunk received from a server in the world, replacing the copy it may have
+    ///! it is lit and meshed like a generated one
+    pub fn insert_chunk(&mut self, chunk_pos: IVec3, chunk_data: Arc<ChunkData>) {
+        self.world_data.insert(chunk_pos, chunk_data);
+        self.world_light.remove(&chunk_pos);
+        if !self.unlit_chunks.contains(&chunk_pos) {
+            self.unlit_chunks.push(chunk_pos);
+        }
+        // the meshes around it were built from the replaced copy
+        let neighbours = ADJACENT_CHUNK_DIRECTIONS.iter().map(|dir| chunk_pos + *dir);
+        self.requeue_light_changes(neighbours.collect());
+    }
+
+    ///! drop a chunk the server stopped sending edits for, even if a scanner still wants it
+    ///! its mesh goes with it, and is built again if the server sends the chunk back
+    pub fn forget_chunk(&mut self, chunk_pos: IVec3) {
+        self.world_data.remove(&chunk_pos);
+        self.world_light.remove(&chunk_pos);
+        self.unlit_chunks.retain(|pos| *pos != chunk_pos);
+        self.chunk_modifications.remove(&chunk_pos);
+        self.cancel_mesh_task(chunk_pos);
+        self.unload_mesh_queue.push(chunk_pos);
+        if self.mesh_refs.contains_key(&chunk_pos) && !self.load_mesh_queue.contains(&chunk_pos) {
+            self.load_mesh_queue.push(chunk_pos);
+        }
+    }
+
+    ///! stop generating a chunk no scanner wants anymore
+    ///! dropping a task cancels it, the generator isn't interrupted if it is already running
+    pub fn cancel_data_task(&mut self, chunk_pos: IVec3) {
+        if self.data_tasks.remove(&chunk_pos).is_some() {
+            self.task_metrics.data_cancelled += 1;
+        }
+    }
+
+    ///! stop meshing a chunk no scanner wants anymore
+    pub fn cancel_mesh_task(&mut self, chunk_pos: IVec3) {
+        if self.mesh_tasks.remove(&chunk_pos).is_some() {
+            self.task_metrics.mesh_cancelled += 1;
+        }
+    }
+
+    ///! remesh chunks whose level of detail changed since they were meshed,
+    ///! called when a scanner moved to another chunk
+    pub fn requeue_lod_changes(&mut self, scan_positions: &[IVec3]) {
+        let Engine {
+            chunk_lods,
+            load_mesh_queue,
+            ..
+        } = self;
+        for (chunk_pos, lod) in chunk_lods.iter() {
+            let wanted =
+                Lod::from_distance_squared(closest_distance_squared(*chunk_pos, scan_positions));
+            if wanted != *lod && !load_mesh_queue.contains(chunk_pos) {
+                load_mesh_queue.push(*chunk_pos);
+            }
+        }
+    }
+
+    ///! remesh the meshed chunks whose light changed, meshes being built for them are out of date
+    pub fn requeue_light_changes(&mut self, changed: HashSet<IVec3>) {
+        for chunk_pos in changed {
+            let meshed = self.chunk_entities.contains_key(&chunk_pos)
+                || self.mesh_tasks.contains_key(&chunk_pos);
+            if !meshed {
+                continue;
+            }
+            *self.mesh_versions.entry(chunk_pos).or_default() += 1;
+            if !self.load_mesh_queue.contains(&chunk_pos) {
+                self.load_mesh_queue.push(chunk_pos);
+            }
+        }
+    }
+
+    ///! drop all light and light every loaded chunk again, when blocks changed how they light
+    pub fn relight_all(&mut self) {
+        self.world_light.clear();
+        self.unlit_chunks = self.world_data.keys().copied().collect();
+    }
+
+    ///! write every dirty chunk to disk
+    pub fn save_all(&mut self) {
+        let dirty = self.dirty_chunks.iter().copied().collect::<Vec<_>>();
+        for chunk_pos in dirty {
+            self.save_chunk(chunk_pos);
+        }
+    }
+
     pub fn unload_all_meshes(&mut self, scanner: &Scanner, scanner_transform: &GlobalTransform) {
         // stop all any current proccessing
         self.load_mesh_queue.clear();
+        self.task_metrics.mesh_cancelled += self.mesh_tasks.len() as u64;
         self.mesh_tasks.clear();
-        let scan_pos =
-            ((scanner_transform.translation() - Vec3::splat(16.0)) * (1.0 / 32.0)).as_ivec3();
+        // only chunks that still have a mesh keep their connections
+        let Engine {
+            chunk_entities,
+            chunk_connections,
+            ..
+        } = self;
+        chunk_connections.retain(|chunk_pos, _| chunk_entities.contains_key(chunk_pos));
+        let scan_pos = scanner_chunk_pos(scanner_transform);
         for offset in &scanner.mesh_sampling_offsets {
             let wpos = scan_pos + *offset;
             self.load_mesh_queue.push(wpos);
@@ -104,19 +321,29 @@ impl Engine {
 }
 
 // start
-pub fn start_modifications(mut voxel_engine: ResMut<Engine>) {
+pub fn start_modifications(
+    mut voxel_engine: ResMut<Engine>,
+    mut block_changed: EventWriter<BlockChanged>,
+    registry: Res<BlockRegistry>,
+) {
     let Engine {
         world_data,
+        world_light,
         chunk_modifications,
         load_mesh_queue,
+        dirty_chunks,
+        mesh_versions,
+        mesh_refs,
         ..
     } = voxel_engine.as_mut();
 
+    let mut edited = vec![];
     for (pos, mods) in chunk_modifications.drain() {
         // say i want to load mesh now :)
         let Some(chunk_data) = world_data.get_mut(&pos) else {
             continue;
         };
+        dirty_chunks.insert(pos);
 
         let new_chunk_data = Arc::make_mut(chunk_data);
         let mut adj_chunk_set = HashSet::new();
@@ -125,20 +352,19 @@ pub fn start_modifications(mut voxel_engine: ResMut<Engine>) {
             // Transform position to index in the chunk
             let i = vec3_to_index(local_pos, 32);
 
-            // if the chunk has only one voxel
-            if new_chunk_data.voxels.len() == 1 {
-                let mut voxels = vec![];
-                // Fill the whole chunk with block type from voxel
-                for _ in 0..CHUNK_SIZE3 {
-                    voxels.push(BlockData {
-                        block_type: new_chunk_data.voxels[0].block_type,
-                    });
-                }
-                new_chunk_data.voxels = voxels;
+            let old = new_chunk_data.get_block(i).block_type;
+            if old == block_type {
+                continue;
             }
 
-            // apply modification
-            new_chunk_data.voxels[i].block_type = block_type;
+            // apply modification, a filled chunk gets its voxel indices on the first edit
+            new_chunk_data.set_block(i, BlockData { block_type });
+            block_changed.write(BlockChanged {
+                pos: pos * CHUNK_SIZE_I32 + local_pos,
+                old,
+                new: block_type,
+            });
+            edited.push(pos * CHUNK_SIZE_I32 + local_pos);
 
             // If there is another chunk next to current chunk, we add it to our hashset.
             if let Some(edge_chunk) = get_edging_chunk(local_pos) {
@@ -147,12 +373,54 @@ pub fn start_modifications(mut voxel_engine: ResMut<Engine>) {
         }
 
         // Re-do rendenring of adjascent chunks if relevant
-        for adj_chunk in adj_chunk_set.into_iter() {
-            load_mesh_queue.push(pos + adj_chunk);
+        // meshes already being built for them are out of date
+        // chunks no scanner meshes, on a headless server, are left alone
+        for chunk_pos in adj_chunk_set.into_iter().map(|adj| pos + adj).chain([pos]) {
+            if !mesh_refs.contains_key(&chunk_pos) {
+                continue;
+            }
+            *mesh_versions.entry(chunk_pos).or_default() += 1;
+            load_mesh_queue.push(chunk_pos);
         }
+    }
 
-        load_mesh_queue.push(pos);
+    // relight around the edits, once every edited chunk holds its new blocks
+    let mut light = LightPropagation::new(world_data, world_light, &registry);
+    for world_pos in edited {
+        light.update_voxel(world_pos);
     }
+    let changed = light.changed;
+    voxel_engine.requeue_light_changes(changed);
+}
+
+///! light newly loaded chunks, the closest first, and the highest first among those
+///! so sky light rarely has to be taken back from the chunks below
+pub fn light_chunks(
+    mut voxel_engine: ResMut<Engine>,
+    scanners: Query<&GlobalTransform, With<Scanner>>,
+    registry: Res<BlockRegistry>,
+) {
+    let Engine {
+        world_data,
+        world_light,
+        unlit_chunks,
+        ..
+    } = voxel_engine.as_mut();
+    if unlit_chunks.is_empty() {
+        return;
+    }
+
+    let scan_positions = scanners.iter().map(scanner_chunk_pos).collect::<Vec<_>>();
+    unlit_chunks.sort_by_key(|p| (closest_distance_squared(*p, &scan_positions), -p.y));
+
+    let count = unlit_chunks.len().min(MAX_LIGHT_CHUNKS);
+    let mut light = LightPropagation::new(world_data, world_light, &registry);
+    for chunk_pos in unlit_chunks.drain(0..count) {
+        // chunks unloaded in the meantime are skipped
+        light.light_chunk(chunk_pos);
+    }
+    let changed = light.changed;
+    voxel_engine.requeue_light_changes(changed);
 }
 
 ///! begin data building tasks for chunks in range
@@ -160,60 +428,92 @@ pub fn start_data_tasks(
     mut voxel_engine: ResMut<Engine>,
     scanners: Query<&GlobalTransform, With<Scanner>>,
 ) {
-    //let task_pool = AsyncComputeTaskPool::get();
+    let task_pool = AsyncComputeTaskPool::get();
 
     let Engine {
         load_data_queue,
-        //   data_tasks,
+        data_tasks,
+        generator,
+        storage,
+        streamed,
         ..
     } = voxel_engine.as_mut();
 
-    // Get engine's scanner
-    let scanner_g = scanners.single().unwrap();
+    // the server sends the chunks instead
+    if *streamed {
+        load_data_queue.clear();
+        return;
+    }
 
-    // adjust scanner position (but to what!?)
-    let scan_pos = ((scanner_g.translation() - Vec3::splat(16.0)) * (1.0 / 32.0)).as_ivec3();
+    // Get engine's scanners, there is none until the player has spawned
+    let scan_positions = scanners.iter().map(scanner_chunk_pos).collect::<Vec<_>>();
 
-    // Sort blocks by distance to the scanner (player?)
-    load_data_queue.sort_by(|a, b| {
-        a.distance_squared(scan_pos)
-            .cmp(&b.distance_squared(scan_pos))
-    });
+    // Sort chunks by distance to the closest scanner (player?)
+    load_data_queue.sort_by_key(|p| closest_distance_squared(*p, &scan_positions));
 
     // Tasks left to compute before either the queue is empty or the task vec is full
-    // let tasks_left = (MAX_DATA_TASKS as i32 - data_tasks.len() as i32)
-    //    .min(load_data_queue.len() as i32)
-    //    .max(0) as usize;
+    let tasks_left = (MAX_DATA_TASKS as i32 - data_tasks.len() as i32)
+        .min(load_data_queue.len() as i32)
+        .max(0) as usize;
 
     // Extract elements from load queue and process them
-    //for world_pos in load_data_queue.drain(0..tasks_left) {
-    //    let k = world_pos;
-    //    let task = task_pool.spawn(async move {
-    //        let cd = ChunkData::generate(k);
-    //        cd
-    //    });
-    // add thread amd coords to current tasks
-    //   data_tasks.insert(world_pos, Some(task));
-    //}
+    for world_pos in load_data_queue.drain(0..tasks_left) {
+        let k = world_pos;
+        let generator = Arc::clone(generator);
+        let storage = Arc::clone(storage);
+        let task = task_pool.spawn(async move {
+            // saved chunks take precedence over generated ones
+            match storage.load_chunk(k) {
+                Ok(Some(cd)) => cd,
+                Ok(None) => generator.generate(k),
+                Err(e) => {
+                    error!("failed to load chunk {k}, regenerating it: {e}");
+                    generator.generate(k)
+                }
+            }
+        });
+        // add thread amd coords to current tasks
+        data_tasks.insert(world_pos, Some(task));
+    }
 }
 
 ///! destroy enqueued, chunk data
 pub fn unload_data(mut voxel_engine: ResMut<Engine>) {
-    let Engine {
-        unload_data_queue,
-        world_data,
-        ..
-    } = voxel_engine.as_mut();
+    let unload_data_queue = std::mem::take(&mut voxel_engine.unload_data_queue);
+
+    for chunk_pos in unload_data_queue {
+        // a scanner came back for it
+        if voxel_engine.data_refs.contains_key(&chunk_pos) {
+            continue;
+        }
+        // flush edits before the chunk is forgotten
+        voxel_engine.save_chunk(chunk_pos);
+        voxel_engine.world_data.remove(&chunk_pos);
+        voxel_engine.world_light.remove(&chunk_pos);
+    }
+}
+
+///! pick the world directory from the `--world <dir>` command line argument
+pub fn select_world_storage(mut voxel_engine: ResMut<Engine>) {
+    if let Some(dir) = cli_arg("--world") {
+        info!("using world {dir}");
+        voxel_engine.storage = Arc::new(WorldStorage::new(dir));
+    }
+}
 
-    for chunk_pos in unload_data_queue.drain(..) {
-        world_data.remove(&chunk_pos);
+///! save dirty chunks when the app is closing
+pub fn save_world_on_exit(mut exit_events: EventReader<AppExit>, mut voxel_engine: ResMut<Engine>) {
+    if exit_events.read().last().is_none() {
+        return;
     }
+    voxel_engine.save_all();
 }
 
 ///! begin mesh building tasks for chunks in range
 pub fn start_mesh_tasks(
     mut voxel_engine: ResMut<Engine>,
     scanners: Query<&GlobalTransform, With<Scanner>>,
+    registry: Res<BlockRegistry>,
 ) {
     let task_pool = AsyncComputeTaskPool::get();
 
@@ -221,37 +521,58 @@ pub fn start_mesh_tasks(
         load_mesh_queue,
         mesh_tasks,
         world_data,
-        lod,
+        world_light,
+        mesh_refs,
+        chunk_lods,
+        mesh_versions,
+        task_metrics,
         ..
     } = voxel_engine.as_mut();
 
-    let scanner_g = scanners.single().unwrap();
-
-    let scan_pos = ((scanner_g.translation() - Vec3::splat(16.0)) * (1.0 / 32.0)).as_ivec3();
+    // Get engine's scanners, there is none until the player has spawned
+    let scan_positions = scanners.iter().map(scanner_chunk_pos).collect::<Vec<_>>();
 
-    // Sort blocks by distance to the scanner (player?)
-    load_mesh_queue.sort_by(|a, b| {
-        a.distance_squared(scan_pos)
-            .cmp(&b.distance_squared(scan_pos))
-    });
+    // Sort chunks by distance to the closest scanner (player?)
+    load_mesh_queue.sort_by_key(|p| closest_distance_squared(*p, &scan_positions));
 
     // Tasks left to compute before either the queue is empty or the task vec is full
     let tasks_left = (MAX_MESH_TASKS as i32 - mesh_tasks.len() as i32)
         .min(load_mesh_queue.len() as i32)
         .max(0) as usize;
 
+    let mut retry = vec![];
     for world_pos in load_mesh_queue.drain(0..tasks_left) {
         // for world_pos in load_mesh_queue.drain(..) {
-        let Some(chunks_refs) = ChunksRefs::try_new(world_data, world_pos) else {
+        let Some(chunks_refs) = ChunksRefs::try_new(world_data, world_light, world_pos) else {
+            // being relit, try again later
+            if mesh_refs.contains_key(&world_pos) {
+                retry.push(world_pos);
+            }
             continue;
         };
 
-        let llod = *lod;
-
-        let task = task_pool.spawn(async move { mesher::build_chunk_mesh(&chunks_refs, llod) });
-
-        mesh_tasks.push((world_pos, Some(task)));
+        let llod = Lod::from_distance_squared(closest_distance_squared(world_pos, &scan_positions));
+        chunk_lods.insert(world_pos, llod);
+        let registry = registry.clone();
+
+        let task = task_pool.spawn(async move {
+            let start = Instant::now();
+            let mesh = mesher::build_chunk_mesh(&chunks_refs, llod, &registry);
+            let connections = FaceConnections::from_chunk(&chunks_refs.chunks[13], &registry);
+            (mesh, connections, start.elapsed())
+        });
+
+        let version = mesh_versions.get(&world_pos).copied().unwrap_or(0);
+        let mesh_task = MeshTask {
+            version,
+            task: Some(task),
+        };
+        // the older task is dropped, cancelling it
+        if mesh_tasks.insert(world_pos, mesh_task).is_some() {
+            task_metrics.mesh_superseded += 1;
+        }
     }
+    load_mesh_queue.append(&mut retry);
 }
 
 ///! destroy enqueued, chunk mesh entities
@@ -259,10 +580,24 @@ pub fn unload_mesh(mut commands: Commands, mut voxel_engine: ResMut<Engine>) {
     let Engine {
         unload_mesh_queue,
         chunk_entities,
+        chunk_connections,
+        world_data,
+        mesh_refs,
+        chunk_lods,
+        mesh_versions,
+        vertex_counts,
         ..
     } = voxel_engine.as_mut();
     let mut retry = Vec::new();
     for chunk_pos in unload_mesh_queue.drain(..) {
+        // a scanner came back for it, unless its data is gone
+        if mesh_refs.contains_key(&chunk_pos) && world_data.contains_key(&chunk_pos) {
+            continue;
+        }
+        chunk_lods.remove(&chunk_pos);
+        mesh_versions.remove(&chunk_pos);
+        vertex_counts.remove(&chunk_pos);
+        chunk_connections.remove(&chunk_pos);
         let Some(chunk_id) = chunk_entities.remove(&chunk_pos) else {
             continue;
         };
@@ -279,6 +614,9 @@ pub fn join_data(mut voxel_engine: ResMut<Engine>) {
     let Engine {
         world_data,
         data_tasks,
+        data_refs,
+        task_metrics,
+        unlit_chunks,
         ..
     } = voxel_engine.as_mut();
     for (world_pos, task_option) in data_tasks.iter_mut() {
@@ -294,8 +632,16 @@ pub fn join_data(mut voxel_engine: ResMut<Engine>) {
             continue;
         };
 
+        // every scanner moved away while it was generating, nothing would unload it
+        if !data_refs.contains_key(world_pos) {
+            task_metrics.data_discarded += 1;
+            continue;
+        }
+
         // inert the new chunk in the word
+        task_metrics.data_completed += 1;
         world_data.insert(*world_pos, Arc::new(chunk_data));
+        unlit_chunks.push(*world_pos);
     }
     data_tasks.retain(|_k, op| op.is_some());
 }
@@ -336,60 +682,110 @@ pub fn join_mesh(
     mut voxel_engine: ResMut<Engine>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
+    chunk_material: Res<GlobalChunkMaterial>,
+    chunk_material_wireframe: Res<GlobalChunkWireframeMaterial>,
+    chunk_material_transparent: Res<GlobalChunkTransparentMaterial>,
+    wireframe_mode: Res<ChunkMaterialWireframeMode>,
 ) {
     let Engine {
         mesh_tasks,
         chunk_entities,
+        chunk_connections,
+        mesh_versions,
+        task_metrics,
+        mesh_build_times,
+        vertex_counts,
         ..
     } = voxel_engine.as_mut();
 
     // Iter through meshing tasks
-    for (world_pos, task_option) in mesh_tasks.iter_mut() {
+    for (world_pos, mesh_task) in mesh_tasks.iter_mut() {
+        let task_option = &mut mesh_task.task;
         let Some(mut task) = task_option.take() else {
             // should never happend, because we drop None values later
             warn!("someone modified task?");
             continue;
         };
 
-        let Some(chunk_mesh_option) = block_on(future::poll_once(&mut task)) else {
+        let Some((chunk_meshes, connections, build_time)) = block_on(future::poll_once(&mut task))
+        else {
             // failed polling, keep task alive
             *task_option = Some(task);
             continue;
         };
 
-        let Some(mesh) = chunk_mesh_option else {
+        // the chunk was edited after the task started, a newer mesh is on its way
+        if mesh_task.version < mesh_versions.get(world_pos).copied().unwrap_or(0) {
+            task_metrics.mesh_stale += 1;
             continue;
-        };
-
-        let mut bevy_mesh = Mesh::new(
-            bevy::render::mesh::PrimitiveTopology::TriangleList,
-            RenderAssetUsages::RENDER_WORLD,
-        );
-
-        // give existing chunk mesh attributes to new mesh
-        bevy_mesh.insert_attribute(ATTRIBUTE_VOXEL, mesh.vertices.clone());
-        bevy_mesh.insert_indices(bevy::render::mesh::Indices::U32(
-            mesh.indices.clone().into(),
-        ));
+        }
+        task_metrics.mesh_completed += 1;
+        mesh_build_times.push(build_time);
+        chunk_connections.insert(*world_pos, connections);
 
-        // add the newly created mesh to the game
-        let mesh_handle = meshes.add(bevy_mesh);
+        // despawn chink from the world, with its transparent part
+        if let Some(entity) = chunk_entities.remove(world_pos) {
+            commands.entity(entity).despawn();
+        }
+        vertex_counts.remove(world_pos);
 
-        // despawn chink from the world
-        if let Some(entity) = chunk_entities.get(world_pos) {
-            commands.entity(*entity).despawn();
+        let ChunkMeshes {
+            opaque,
+            transparent,
+        } = chunk_meshes;
+        if opaque.is_none() && transparent.is_none() {
+            continue;
         }
 
         // spawn chunk entity
-        let chunk_entity = commands
-            .spawn((
-                // Aabb::from_min_max(Vec3::ZERO, Vec3::splat(32.0)),
-                Transform::from_translation(world_pos.as_vec3() * Vec3::splat(32.0)),
-                Mesh3d(mesh_handle),
-                // MeshMaterial3d(global_chunk_material.0.clone()),
-            ))
-            .id();
+        let mut chunk_commands = commands.spawn((
+            Transform::from_translation(world_pos.as_vec3() * Vec3::splat(32.0)),
+            Visibility::default(),
+        ));
+        let mut vertex_count = 0;
+        if let Some(mesh) = opaque {
+            vertex_count += mesh.vertices.len();
+            // the mesh has no position attribute bevy could compute it from
+            let (min, max) = vertex_bounds(&mesh.vertices);
+            chunk_commands.insert((
+                Aabb::from_min_max(min, max),
+                Mesh3d(meshes.add(to_bevy_mesh(mesh))),
+            ));
+            match *wireframe_mode {
+                ChunkMaterialWireframeMode::On => {
+                    chunk_commands.insert(chunk_material_wireframe.0.clone())
+                }
+                ChunkMaterialWireframeMode::Off => chunk_commands.insert(chunk_material.0.clone()),
+            };
+        }
+        if let Some(mesh) = transparent {
+            vertex_count += mesh.vertices.len();
+            let (min, max) = vertex_bounds(&mesh.vertices);
+            let origin = Vec3::splat(TRANSPARENT_MESH_ORIGIN);
+            chunk_commands.with_child((
+                Aabb::from_min_max(min - origin, max - origin),
+                TransparentChunkMesh,
+                // transparent meshes are sorted by their origin, put it in the middle of the chunk
+                Transform::from_translation(Vec3::splat(TRANSPARENT_MESH_ORIGIN)),
+                Mesh3d(meshes.add(to_bevy_mesh(mesh))),
+                chunk_material_transparent.0.clone(),
+                NotShadowCaster,
+            ));
+        }
+        let chunk_entity = chunk_commands.id();
         chunk_entities.insert(*world_pos, chunk_entity);
+        vertex_counts.insert(*world_pos, vertex_count);
     }
-    mesh_tasks.retain(|(_p, op)| op.is_some());
+    mesh_tasks.retain(|_p, mesh_task| mesh_task.task.is_some());
+}
+
+///! gpu mesh with the packed voxel vertices
+fn to_bevy_mesh(mesh: ChunkMesh) -> Mesh {
+    let mut bevy_mesh = Mesh::new(
+        bevy::render::mesh::PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    bevy_mesh.insert_attribute(ATTRIBUTE_VOXEL, mesh.vertices);
+    bevy_mesh.insert_indices(bevy::render::mesh::Indices::U32(mesh.indices));
+    bevy_mesh
 }
@@ -0,0 +1,279 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::Write as _,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bevy::{
+    math::{IVec3, Vec2, Vec3},
+    platform::collections::HashMap,
+};
+
+use super::{
+    block::BlockType,
+    block_registry::{BLOCK_DEFINITIONS_PATH, BlockDefinitions, BlockRegistry},
+    chunk::{CHUNK_SIZE_I32, ChunkData, ChunksRefs},
+    engine::{DEFAULT_WORLD_DIR, Lod},
+    face_direction::FaceDir,
+    generator::{NoiseGenerator, WORLD_SEED, WorldGenerator, WorldGenerators},
+    light::{ChunkLight, MAX_LIGHT},
+    mesher::build_chunk_mesh,
+    region::WorldStorage,
+    utils::{assets_path, cli_arg},
+};
+
+///! the first command line argument that runs the exporter instead of the game
+pub const EXPORT_COMMAND: &str = "export";
+
+///! faces in the order of FaceDir::normal_index
+const FACES: [FaceDir; 6] = [
+    FaceDir::Left,
+    FaceDir::Right,
+    FaceDir::Down,
+    FaceDir::Up,
+    FaceDir::Forward,
+    FaceDir::Back,
+];
+
+///! what the export wrote
+#[derive(Debug, Default)]
+pub struct ExportStats {
+    pub chunks: usize,
+    pub quads: usize,
+    pub materials: usize,
+}
+
+///! a chunk vertex unpacked, see make_vertex_u32 and make_vertex_uv_u32
+struct DecodedVertex {
+    ///! world position
+    position: Vec3,
+    normal_index: u32,
+    ///! solid neighbours of the corner, 0 to 3
+    ao: u32,
+    block_type: BlockType,
+    ///! in blocks
+    uv: Vec2,
+}
+
+impl DecodedVertex {
+    fn new(chunk_pos: IVec3, [packed, packed_uv]: [u32; 2]) -> Self {
+        let local = IVec3::new(
+            (packed & 63) as i32,
+            ((packed >> 6) & 63) as i32,
+            ((packed >> 12) & 63) as i32,
+        );
+        Self {
+            position: (chunk_pos * CHUNK_SIZE_I32 + local).as_vec3(),
+            ao: (packed >> 18) & 7,
+            normal_index: (packed >> 21) & 7,
+            block_type: BlockType(((packed >> 25) & 127) as u16),
+            uv: Vec2::new((packed_uv & 63) as f32, ((packed_uv >> 6) & 63) as f32),
+        }
+    }
+}
+
+///! mesh the chunks from region_min to region_max included, in chunk coordinates, and write them
+///! as a wavefront obj with a material per block type next to it, with the mtl extension.
+///! faces of blocks whose top, side and bottom look different get a material per face.
+///! ambient occlusion is written as vertex colors.
+///! chunks need their neighbours in world_data to be meshed, the others are skipped.
+pub fn export_obj(
+    world_data: &HashMap<IVec3, Arc<ChunkData>>,
+    region_min: IVec3,
+    region_max: IVec3,
+    registry: &BlockRegistry,
+    obj_path: &Path,
+) -> io::Result<ExportStats> {
+    // exported meshes are fully lit, light isn't part of the obj
+    let full_light = Arc::new(ChunkLight::filled(MAX_LIGHT, 0));
+    let world_light = world_data
+        .keys()
+        .map(|chunk_pos| (*chunk_pos, Arc::clone(&full_light)))
+        .collect::<HashMap<_, _>>();
+
+    let mut stats = ExportStats::default();
+    let mut vertices = String::new();
+    let mut vertex_count = 0;
+    // material name -> the face it's for and its quads, as 1 based indices of their vertices
+    // and of their normal
+    let mut faces: BTreeMap<String, (BlockType, FaceDir, Vec<([usize; 4], usize)>)> =
+        BTreeMap::new();
+
+    for z in region_min.z..=region_max.z {
+        for y in region_min.y..=region_max.y {
+            for x in region_min.x..=region_max.x {
+                let chunk_pos = IVec3::new(x, y, z);
+                let Some(chunks_refs) = ChunksRefs::try_new(world_data, &world_light, chunk_pos)
+                else {
+                    continue;
+                };
+                stats.chunks += 1;
+                let meshes = build_chunk_mesh(&chunks_refs, Lod::L32, registry);
+                for mesh in [meshes.opaque, meshes.transparent].into_iter().flatten() {
+                    for quad in mesh.vertices.chunks_exact(4) {
+                        let quad = [0, 1, 2, 3].map(|i| DecodedVertex::new(chunk_pos, quad[i]));
+                        let mut indices = [0; 4];
+                        for (i, vertex) in quad.iter().enumerate() {
+                            let shade = 1.0 - vertex.ao as f32 * 0.2;
+                            let _ = writeln!(
+                                vertices,
+                                "v {} {} {} {shade} {shade} {shade}",
+                                vertex.position.x, vertex.position.y, vertex.position.z
+                            );
+                            // obj textures start at the bottom
+                            let _ = writeln!(vertices, "vt {} {}", vertex.uv.x, 1.0 - vertex.uv.y);
+                            vertex_count += 1;
+                            indices[i] = vertex_count;
+                        }
+                        let block_type = quad[0].block_type;
+                        let face = FACES[quad[0].normal_index as usize];
+                        faces
+                            .entry(material_name(registry, block_type, face))
+                            .or_insert_with(|| (block_type, face, Vec::new()))
+                            .2
+                            .push((indices, quad[0].normal_index as usize + 1));
+                        stats.quads += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    let mtl_path = obj_path.with_extension("mtl");
+    let mut obj = BufWriter::new(fs::File::create(obj_path)?);
+    if let Some(name) = mtl_path.file_name() {
+        writeln!(obj, "mtllib {}", name.to_string_lossy())?;
+    }
+    for face in FACES {
+        let normal = face.air_sample_dir();
+        writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+    }
+    obj.write_all(vertices.as_bytes())?;
+    for (material, (_, _, quads)) in faces.iter() {
+        writeln!(obj, "usemtl {material}")?;
+        for (indices, normal) in quads {
+            // each vertex has its own texture coordinate, at the same index
+            let corners = indices
+                .iter()
+                .map(|i| format!("{i}/{i}/{normal}"))
+                .collect::<Vec<_>>();
+            writeln!(obj, "f {}", corners.join(" "))?;
+        }
+    }
+    obj.flush()?;
+
+    let mut mtl = BufWriter::new(fs::File::create(&mtl_path)?);
+    for (material, (block_type, face, _)) in faces.iter() {
+        write_material(&mut mtl, registry, material, *block_type, *face)?;
+        stats.materials += 1;
+    }
+    mtl.flush()?;
+    Ok(stats)
+}
+
+///! the block's name, suffixed with the face if its faces don't all look the same
+fn material_name(registry: &BlockRegistry, block_type: BlockType, face: FaceDir) -> String {
+    let name = registry
+        .get(block_type)
+        .map_or_else(|| format!("block_{}", block_type.id()), |b| b.name.clone());
+    let top = registry.face_layer(block_type, FaceDir::Up);
+    let side = registry.face_layer(block_type, FaceDir::Left);
+    let bottom = registry.face_layer(block_type, FaceDir::Down);
+    if top == side && side == bottom {
+        return name;
+    }
+    match face {
+        FaceDir::Up => format!("{name}_top"),
+        FaceDir::Down => format!("{name}_bottom"),
+        _ => format!("{name}_side"),
+    }
+}
+
+fn write_material(
+    out: &mut impl Write,
+    registry: &BlockRegistry,
+    material: &str,
+    block_type: BlockType,
+    face: FaceDir,
+) -> io::Result<()> {
+    writeln!(out, "newmtl {material}")?;
+    let Some(definition) = registry.get(block_type) else {
+        return writeln!(out, "Kd 1 1 1\n");
+    };
+    let [r, g, b, a] = definition.color;
+    match definition.textures.face(face) {
+        Some(texture) => {
+            // absolute, so the obj can be moved around
+            let path = assets_path().join(texture);
+            let path = fs::canonicalize(&path).unwrap_or(path);
+            writeln!(out, "Kd 1 1 1")?;
+            writeln!(out, "map_Kd {}", path.display())?;
+        }
+        None => writeln!(out, "Kd {r} {g} {b}")?,
+    }
+    writeln!(out, "d {a}\n")
+}
+
+///! `guncruft export --from x,y,z --to x,y,z [--out world.obj] [--world dir] [--generator name]`
+///! loads the region from the world directory, generates the chunks that were never saved,
+///! and exports it with export_obj, without opening a window
+pub fn run_export_command() -> Result<(), Box<dyn Error>> {
+    let parse_chunk_pos = |flag: &str| -> Result<IVec3, Box<dyn Error>> {
+        let arg = cli_arg(flag).ok_or(format!("missing {flag} x,y,z, in chunk coordinates"))?;
+        let coords = arg
+            .split(',')
+            .map(|c| c.trim().parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()?;
+        match coords[..] {
+            [x, y, z] => Ok(IVec3::new(x, y, z)),
+            _ => Err(format!("{flag} expects x,y,z, got {arg}").into()),
+        }
+    };
+    let from = parse_chunk_pos("--from")?;
+    let to = parse_chunk_pos("--to")?;
+    let (region_min, region_max) = (from.min(to), from.max(to));
+    let out = PathBuf::from(cli_arg("--out").unwrap_or("world.obj".into()));
+
+    let registry = {
+        let path = assets_path().join(BLOCK_DEFINITIONS_PATH);
+        let definitions = ron::de::from_bytes::<BlockDefinitions>(&fs::read(&path)?)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        BlockRegistry::from_definitions(definitions.blocks)
+    };
+    let generator: Arc<dyn WorldGenerator> = match cli_arg("--generator") {
+        Some(name) => WorldGenerators::default()
+            .get(&name)
+            .ok_or(format!("unknown world generator {name}"))?,
+        None => Arc::new(NoiseGenerator::new(WORLD_SEED)),
+    };
+    let storage = WorldStorage::new(cli_arg("--world").unwrap_or(DEFAULT_WORLD_DIR.into()));
+
+    // the region and the neighbours its border chunks are meshed against
+    let mut world_data = HashMap::new();
+    for z in region_min.z - 1..=region_max.z + 1 {
+        for y in region_min.y - 1..=region_max.y + 1 {
+            for x in region_min.x - 1..=region_max.x + 1 {
+                let chunk_pos = IVec3::new(x, y, z);
+                let chunk_data = match storage.load_chunk(chunk_pos)? {
+                    Some(chunk_data) => chunk_data,
+                    None => generator.generate(chunk_pos),
+                };
+                world_data.insert(chunk_pos, Arc::new(chunk_data));
+            }
+        }
+    }
+
+    let stats = export_obj(&world_data, region_min, region_max, &registry, &out)?;
+    println!(
+        "exported {} chunks, {} quads and {} materials to {}",
+        stats.chunks,
+        stats.quads,
+        stats.materials,
+        out.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use super::{
+    block::BlockType,
+    chunk::{CHUNK_SIZE_I32, CHUNK_SIZE2, ChunkData},
+    engine::Engine,
+    noise::ValueNoise,
+    utils::cli_arg,
+};
+
+pub const WORLD_SEED: u64 = 0x6775_6e63_7275_6674;
+
+///! fills chunks with voxels
+///! generators run inside async compute tasks, so they have to be shareable between threads
+pub trait WorldGenerator: Send + Sync {
+    fn generate(&self, chunk_pos: IVec3) -> ChunkData;
+}
+
+///! every generator that can be selected by name
+#[derive(Resource)]
+pub struct WorldGenerators {
+    generators: HashMap<String, Arc<dyn WorldGenerator>>,
+}
+
+impl Default for WorldGenerators {
+    fn default() -> Self {
+        let mut generators = WorldGenerators {
+            generators: HashMap::new(),
+        };
+        generators.register("flat", FlatGenerator::default());
+        generators.register("checkerboard", CheckerboardGenerator::default());
+        generators.register("noise", NoiseGenerator::new(WORLD_SEED));
+        generators
+    }
+}
+
+impl WorldGenerators {
+    ///! make a generator selectable, replaces any generator with the same name
+    pub fn register(&mut self, name: impl Into<String>, generator: impl WorldGenerator + 'static) {
+        self.generators.insert(name.into(), Arc::new(generator));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn WorldGenerator>> {
+        self.generators.get(name).cloned()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.generators.keys().map(String::as_str)
+    }
+}
+
+///! pick the active generator from the `--generator <name>` command line argument
+pub fn select_world_generator(generators: Res<WorldGenerators>, mut voxel_engine: ResMut<Engine>) {
+    let Some(name) = cli_arg("--generator") else {
+        return;
+    };
+
+    match generators.get(&name) {
+        Some(generator) => {
+            info!("using world generator {name}");
+            voxel_engine.generator = generator;
+        }
+        None => {
+            let known = generators.names().collect::<Vec<_>>().join(", ");
+            warn!("unknown world generator {name}, expected one of: {known}");
+        }
+    }
+}
+
+///! an endless plane, the surface block on top and fill below
+pub struct FlatGenerator {
+    pub height: i32,
+    pub surface: BlockType,
+    pub fill: BlockType,
+}
+
+impl Default for FlatGenerator {
+    fn default() -> Self {
+        Self {
+            height: -1,
+            surface: BlockType::GRASS,
+            fill: BlockType::DIRT,
+        }
+    }
+}
+
+impl WorldGenerator for FlatGenerator {
+    fn generate(&self, chunk_pos: IVec3) -> ChunkData {
+        let bottom = chunk_pos.y * CHUNK_SIZE_I32;
+        if bottom > self.height {
+            return ChunkData::filled(BlockType::AIR);
+        }
+        if bottom + CHUNK_SIZE_I32 - 1 < self.height {
+            return ChunkData::filled(self.fill);
+        }
+        ChunkData::from_fn(chunk_pos, |pos| {
+            if pos.y > self.height {
+                BlockType::AIR
+            } else if pos.y == self.height {
+                self.surface
+            } else {
+                self.fill
+            }
+        })
+    }
+}
+
+///! a flat test arena whose surface alternates between two blocks
+pub struct CheckerboardGenerator {
+    pub height: i32,
+    ///! width of a tile, in blocks
+    pub tile_size: i32,
+    pub even: BlockType,
+    pub odd: BlockType,
+}
+
+impl Default for CheckerboardGenerator {
+    fn default() -> Self {
+        Self {
+            height: -2,
+            tile_size: 2,
+            even: BlockType::DIRT,
+            odd: BlockType::GRASS,
+        }
+    }
+}
+
+impl WorldGenerator for CheckerboardGenerator {
+    fn generate(&self, chunk_pos: IVec3) -> ChunkData {
+        let bottom = chunk_pos.y * CHUNK_SIZE_I32;
+        if bottom > self.height {
+            return ChunkData::filled(BlockType::AIR);
+        }
+        if bottom + CHUNK_SIZE_I32 - 1 < self.height {
+            return ChunkData::filled(self.even);
+        }
+        ChunkData::from_fn(chunk_pos, |pos| {
+            if pos.y > self.height {
+                BlockType::AIR
+            } else if pos.y < self.height {
+                self.even
+            } else if (pos.x.div_euclid(self.tile_size) + pos.z.div_euclid(self.tile_size)) % 2 == 0
+            {
+                self.even
+            } else {
+                self.odd
+            }
+        })
+    }
+}
+
+///! rolling hills from a seeded heightmap
+///! grass on the surface, dirt underneath, air above
+pub struct NoiseGenerator {
+    noise: ValueNoise,
+    ///! world units covered by one noise lattice cell
+    pub scale: f32,
+    ///! maximum distance of the surface from y = 0
+    pub amplitude: f32,
+    pub octaves: u32,
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            noise: ValueNoise::new(seed),
+            scale: 64.0,
+            amplitude: 16.0,
+            octaves: 4,
+        }
+    }
+
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let h = self
+            .noise
+            .fbm(x as f32 / self.scale, z as f32 / self.scale, self.octaves);
+        (h * self.amplitude).floor() as i32
+    }
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn generate(&self, chunk_pos: IVec3) -> ChunkData {
+        let origin = chunk_pos * CHUNK_SIZE_I32;
+
+        // one height per column, sampled in world space so chunks line up
+        let mut heights = [0i32; CHUNK_SIZE2];
+        for z in 0..CHUNK_SIZE_I32 {
+            for x in 0..CHUNK_SIZE_I32 {
+                heights[(z * CHUNK_SIZE_I32 + x) as usize] =
+                    self.height_at(origin.x + x, origin.z + z);
+            }
+        }
+
+        // early exit, chunk is entirely above or below the surface
+        let min_height = *heights.iter().min().unwrap();
+        let max_height = *heights.iter().max().unwrap();
+        if origin.y > max_height {
+            return ChunkData::filled(BlockType::AIR);
+        }
+        if origin.y + CHUNK_SIZE_I32 - 1 < min_height {
+            return ChunkData::filled(BlockType::DIRT);
+        }
+
+        ChunkData::from_fn(chunk_pos, |pos| {
+            let local = pos - origin;
+            let height = heights[(local.z * CHUNK_SIZE_I32 + local.x) as usize];
+            if pos.y > height {
+                BlockType::AIR
+            } else if pos.y == height {
+                BlockType::GRASS
+            } else {
+                BlockType::DIRT
+            }
+        })
+    }
+}
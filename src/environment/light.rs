@@ -0,0 +1,422 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use bevy::{
+    math::{IVec3, ivec3},
+    platform::collections::{HashMap, HashSet},
+};
+
+use super::{
+    block::BlockType,
+    block_registry::BlockRegistry,
+    chunk::{CHUNK_SIZE_I32, CHUNK_SIZE2_I32, CHUNK_SIZE3, ChunkData},
+    utils::{index_to_ivec3_bounds, vec3_to_index, world_to_chunk},
+};
+
+///! brightest light level, of open sky and of the brightest blocks
+pub const MAX_LIGHT: u8 = 15;
+
+///! newly loaded chunks lit per frame, lighting runs on the main thread
+pub const MAX_LIGHT_CHUNKS: usize = 16;
+
+const NEIGHBOURS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Y,
+    IVec3::Y,
+    IVec3::NEG_Z,
+    IVec3::Z,
+];
+
+///! sky and block light of every voxel of a chunk, 4 bits each, sky in the high bits
+///! a chunk where every voxel has the same light stores no levels
+#[derive(Clone)]
+pub struct ChunkLight {
+    uniform: u8,
+    levels: Vec<u8>,
+}
+
+impl ChunkLight {
+    pub fn filled(sky: u8, block: u8) -> Self {
+        Self {
+            uniform: sky << 4 | block,
+            levels: vec![],
+        }
+    }
+
+    ///! collapses to the filled form if all voxels have the same light
+    pub fn from_levels(levels: Vec<u8>) -> Self {
+        if levels.iter().all(|l| *l == levels[0]) {
+            return Self {
+                uniform: levels[0],
+                levels: vec![],
+            };
+        }
+        Self { uniform: 0, levels }
+    }
+
+    ///! both levels packed, see LightChannel to read them
+    #[inline]
+    pub fn get(&self, index: usize) -> u8 {
+        if self.levels.is_empty() {
+            self.uniform
+        } else {
+            self.levels[index]
+        }
+    }
+
+    #[inline]
+    pub fn sky(&self, index: usize) -> u8 {
+        LightChannel::Sky.level(self.get(index))
+    }
+
+    #[inline]
+    pub fn block(&self, index: usize) -> u8 {
+        LightChannel::Block.level(self.get(index))
+    }
+
+    ///! a filled chunk gets its levels on the first change
+    fn set(&mut self, index: usize, packed: u8) {
+        if self.levels.is_empty() {
+            if packed == self.uniform {
+                return;
+            }
+            self.levels = vec![self.uniform; CHUNK_SIZE3];
+        }
+        self.levels[index] = packed;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LightChannel {
+    ///! falls straight down from open sky without fading
+    Sky,
+    ///! emitted by blocks, see BlockDefinition::light_emission
+    Block,
+}
+
+impl LightChannel {
+    #[inline]
+    pub fn level(self, packed: u8) -> u8 {
+        match self {
+            LightChannel::Sky => packed >> 4,
+            LightChannel::Block => packed & 0xf,
+        }
+    }
+
+    #[inline]
+    fn with_level(self, packed: u8, level: u8) -> u8 {
+        match self {
+            LightChannel::Sky => packed & 0xf | level << 4,
+            LightChannel::Block => packed & 0xf0 | level,
+        }
+    }
+
+    ///! light reaching a neighbour in direction dir, sky light falls down without fading
+    #[inline]
+    fn spread(self, level: u8, dir: IVec3) -> u8 {
+        if self == LightChannel::Sky && dir == IVec3::NEG_Y && level == MAX_LIGHT {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+///! flood fills light over the loaded chunks, across their borders
+///! light stops at chunks that aren't lit yet, they pull it in when they get lit
+pub struct LightPropagation<'a> {
+    world_data: &'a HashMap<IVec3, Arc<ChunkData>>,
+    world_light: &'a mut HashMap<IVec3, Arc<ChunkLight>>,
+    registry: &'a BlockRegistry,
+    ///! chunks whose light changed, or whose neighbours' light next to them did
+    pub changed: HashSet<IVec3>,
+}
+
+impl<'a> LightPropagation<'a> {
+    pub fn new(
+        world_data: &'a HashMap<IVec3, Arc<ChunkData>>,
+        world_light: &'a mut HashMap<IVec3, Arc<ChunkLight>>,
+        registry: &'a BlockRegistry,
+    ) -> Self {
+        Self {
+            world_data,
+            world_light,
+            registry,
+            changed: HashSet::new(),
+        }
+    }
+
+    ///! light a newly loaded chunk, and exchange light with its lit neighbours
+    ///! a chunk whose upper neighbour isn't lit yet is assumed to be under open sky,
+    ///! the sky light is taken back once that neighbour gets lit
+    pub fn light_chunk(&mut self, chunk_pos: IVec3) {
+        if self.world_light.contains_key(&chunk_pos) {
+            return;
+        }
+        let Some(chunk_data) = self.world_data.get(&chunk_pos).cloned() else {
+            return;
+        };
+        let origin = chunk_pos * CHUNK_SIZE_I32;
+        let mut sky_queue = VecDeque::new();
+        let mut block_queue = VecDeque::new();
+
+        let filled_opaque = chunk_data.get_block_if_filled().is_some_and(|b| {
+            !self.registry.is_transparent(b.block_type)
+                && self.registry.light_emission(b.block_type) == 0
+        });
+        if filled_opaque {
+            // no light gets in or out
+            self.world_light
+                .insert(chunk_pos, Arc::new(ChunkLight::filled(0, 0)));
+        } else {
+            let above = self.world_light.get(&(chunk_pos + IVec3::Y)).cloned();
+            let mut levels = vec![0u8; CHUNK_SIZE3];
+            for z in 0..CHUNK_SIZE_I32 {
+                for x in 0..CHUNK_SIZE_I32 {
+                    // sky light falls straight down until something opaque stops it
+                    let mut sky = match &above {
+                        Some(above) => above.sky(vec3_to_index(ivec3(x, 0, z), CHUNK_SIZE_I32)),
+                        None => MAX_LIGHT,
+                    };
+                    if sky != MAX_LIGHT {
+                        sky = 0;
+                    }
+                    for y in (0..CHUNK_SIZE_I32).rev() {
+                        let i = vec3_to_index(ivec3(x, y, z), CHUNK_SIZE_I32);
+                        let block_type = chunk_data.get_block(i).block_type;
+                        if !self.registry.is_transparent(block_type) {
+                            sky = 0;
+                        }
+                        let emission = self.registry.light_emission(block_type);
+                        if emission > 0 {
+                            block_queue.push_back(origin + ivec3(x, y, z));
+                        }
+                        levels[i] = sky << 4 | emission;
+                    }
+                }
+            }
+
+            // sky light spreads sideways from the edges of the lit columns
+            for i in 0..CHUNK_SIZE3 {
+                if LightChannel::Sky.level(levels[i]) != MAX_LIGHT {
+                    continue;
+                }
+                let pos = index_to_ivec3_bounds(i as i32, CHUNK_SIZE_I32);
+                let on_border = pos.x == 0
+                    || pos.z == 0
+                    || pos.y == 0
+                    || pos.x == CHUNK_SIZE_I32 - 1
+                    || pos.z == CHUNK_SIZE_I32 - 1;
+                let next_to_shade = !on_border
+                    && [IVec3::NEG_X, IVec3::X, IVec3::NEG_Z, IVec3::Z]
+                        .iter()
+                        .any(|dir| {
+                            let n = vec3_to_index(pos + *dir, CHUNK_SIZE_I32);
+                            LightChannel::Sky.level(levels[n]) != MAX_LIGHT
+                        });
+                if on_border || next_to_shade {
+                    sky_queue.push_back(origin + pos);
+                }
+            }
+            self.world_light
+                .insert(chunk_pos, Arc::new(ChunkLight::from_levels(levels)));
+        }
+        self.changed.insert(chunk_pos);
+        for dir in NEIGHBOURS {
+            self.changed.insert(chunk_pos + dir);
+        }
+
+        // light of the lit neighbours flows in, unless there is nothing to light
+        for dir in NEIGHBOURS {
+            if filled_opaque || !self.world_light.contains_key(&(chunk_pos + dir)) {
+                continue;
+            }
+            for local in face_voxels(dir) {
+                let pos = origin + local + dir;
+                sky_queue.push_back(pos);
+                block_queue.push_back(pos);
+            }
+        }
+
+        // the chunk below assumed open sky, take it back where this chunk covers it
+        if self.world_light.contains_key(&(chunk_pos - IVec3::Y)) {
+            let mut removals = VecDeque::new();
+            for local in face_voxels(IVec3::NEG_Y) {
+                let pos = origin + local;
+                let below = pos - IVec3::Y;
+                let sky = |light: Option<u8>| light.map(|l| LightChannel::Sky.level(l));
+                if sky(self.light(pos)) != Some(MAX_LIGHT)
+                    && sky(self.light(below)) == Some(MAX_LIGHT)
+                {
+                    self.set(below, LightChannel::Sky, 0);
+                    removals.push_back((below, MAX_LIGHT));
+                }
+            }
+            sky_queue.extend(self.remove(LightChannel::Sky, removals));
+        }
+
+        self.propagate(LightChannel::Sky, sky_queue);
+        self.propagate(LightChannel::Block, block_queue);
+    }
+
+    ///! relight around a voxel whose block changed
+    pub fn update_voxel(&mut self, world_pos: IVec3) {
+        let Some(block_type) = self.block_type(world_pos) else {
+            return;
+        };
+        for channel in [LightChannel::Sky, LightChannel::Block] {
+            let Some(packed) = self.light(world_pos) else {
+                return;
+            };
+            // darken everything the voxel used to light
+            self.set(world_pos, channel, 0);
+            let mut refill = self.remove(
+                channel,
+                VecDeque::from([(world_pos, channel.level(packed))]),
+            );
+
+            if channel == LightChannel::Block {
+                let emission = self.registry.light_emission(block_type);
+                if emission > 0 {
+                    self.set(world_pos, channel, emission);
+                    refill.push_back(world_pos);
+                }
+            }
+            // then let the light of the neighbours flow back in
+            refill.extend(NEIGHBOURS.iter().map(|dir| world_pos + *dir));
+            self.propagate(channel, refill);
+        }
+    }
+
+    ///! packed light of a voxel, None if its chunk isn't lit
+    #[inline]
+    fn light(&self, world_pos: IVec3) -> Option<u8> {
+        let (chunk_pos, local) = world_to_chunk(world_pos);
+        let light = self.world_light.get(&chunk_pos)?;
+        Some(light.get(vec3_to_index(local, CHUNK_SIZE_I32)))
+    }
+
+    #[inline]
+    fn block_type(&self, world_pos: IVec3) -> Option<BlockType> {
+        let (chunk_pos, local) = world_to_chunk(world_pos);
+        let chunk_data = self.world_data.get(&chunk_pos)?;
+        Some(
+            chunk_data
+                .get_block(vec3_to_index(local, CHUNK_SIZE_I32))
+                .block_type,
+        )
+    }
+
+    fn set(&mut self, world_pos: IVec3, channel: LightChannel, level: u8) {
+        let (chunk_pos, local) = world_to_chunk(world_pos);
+        let Some(light) = self.world_light.get_mut(&chunk_pos) else {
+            return;
+        };
+        let i = vec3_to_index(local, CHUNK_SIZE_I32);
+        let packed = light.get(i);
+        if channel.level(packed) == level {
+            return;
+        }
+        Arc::make_mut(light).set(i, channel.with_level(packed, level));
+
+        // meshes sample the light in front of their faces, one voxel into the neighbours
+        self.changed.insert(chunk_pos);
+        for axis in 0..3 {
+            if local[axis] == 0 || local[axis] == CHUNK_SIZE_I32 - 1 {
+                let mut neighbour = chunk_pos;
+                neighbour[axis] += if local[axis] == 0 { -1 } else { 1 };
+                self.changed.insert(neighbour);
+            }
+        }
+    }
+
+    ///! spread light from the queued voxels to their darker see-through neighbours
+    fn propagate(&mut self, channel: LightChannel, mut queue: VecDeque<IVec3>) {
+        while let Some(pos) = queue.pop_front() {
+            let Some(packed) = self.light(pos) else {
+                continue;
+            };
+            let level = channel.level(packed);
+            if level <= 1 {
+                continue;
+            }
+            for dir in NEIGHBOURS {
+                let next = pos + dir;
+                let spread = channel.spread(level, dir);
+                let Some(next_packed) = self.light(next) else {
+                    continue;
+                };
+                if channel.level(next_packed) >= spread {
+                    continue;
+                }
+                if !self
+                    .block_type(next)
+                    .is_some_and(|b| self.registry.is_transparent(b))
+                {
+                    continue;
+                }
+                self.set(next, channel, spread);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    ///! darken the voxels that were lit through the queued voxels and their former levels,
+    ///! returns the voxels lit from elsewhere, that have to spread their light again
+    fn remove(
+        &mut self,
+        channel: LightChannel,
+        mut queue: VecDeque<(IVec3, u8)>,
+    ) -> VecDeque<IVec3> {
+        let mut refill = VecDeque::new();
+        while let Some((pos, old)) = queue.pop_front() {
+            for dir in NEIGHBOURS {
+                let next = pos + dir;
+                let Some(next_packed) = self.light(next) else {
+                    continue;
+                };
+                let level = channel.level(next_packed);
+                if level == 0 {
+                    continue;
+                }
+                let falling_sky = channel.spread(old, dir) == MAX_LIGHT && level == MAX_LIGHT;
+                if level >= old && !falling_sky {
+                    refill.push_back(next);
+                    continue;
+                }
+                self.set(next, channel, 0);
+                queue.push_back((next, level));
+                // light sources light themselves back up
+                if channel == LightChannel::Block {
+                    let emission = self
+                        .block_type(next)
+                        .map_or(0, |b| self.registry.light_emission(b));
+                    if emission > 0 {
+                        self.set(next, channel, emission);
+                        refill.push_back(next);
+                    }
+                }
+            }
+        }
+        refill
+    }
+}
+
+///! local positions of the layer of voxels of a chunk facing direction dir
+fn face_voxels(dir: IVec3) -> impl Iterator<Item = IVec3> {
+    let axis = if dir.x != 0 {
+        0
+    } else if dir.y != 0 {
+        1
+    } else {
+        2
+    };
+    let edge = if dir[axis] < 0 { 0 } else { CHUNK_SIZE_I32 - 1 };
+    (0..CHUNK_SIZE2_I32).map(move |i| {
+        let mut pos = IVec3::ZERO;
+        pos[axis] = edge;
+        pos[(axis + 1) % 3] = i % CHUNK_SIZE_I32;
+        pos[(axis + 2) % 3] = i / CHUNK_SIZE_I32;
+        pos
+    })
+}
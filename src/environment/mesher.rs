@@ -1,39 +1,77 @@
 use std::collections::VecDeque;
 
 use bevy::{
-    math::{IVec3, ivec3},
+    math::{IVec3, ivec3, uvec2},
     platform::collections::HashMap,
 };
 
 use crate::environment::{
-    block::BlockData,
-    chunk::{CHUNK_SIZE, CHUNK_SIZE_P, CHUNK_SIZE3},
+    block::{BlockData, BlockType},
+    block_registry::BlockRegistry,
+    chunk::{CHUNK_SIZE, CHUNK_SIZE_P},
     face_direction::FaceDir,
     scanner::ADJACENT_AO_DIRS,
-    utils::{generate_indices, vec3_to_index},
+    utils::{generate_indices, index_to_ivec3_bounds, vec3_to_index},
 };
 
-use super::{chunk::ChunksRefs, engine::Lod, utils::make_vertex_u32};
+use super::{
+    chunk::{ChunkData, ChunksRefs},
+    engine::Lod,
+    utils::{make_vertex_u32, make_vertex_uv_u32},
+};
 
 ///! gpu ready mesh payload
 #[derive(Default)]
 pub struct ChunkMesh {
     pub indices: Vec<u32>,
-    pub vertices: Vec<u32>,
+    ///! packed position, ao, normal and block type, then uv, texture layer and light
+    pub vertices: Vec<[u32; 2]>,
+}
+
+impl ChunkMesh {
+    ///! None if there are no faces
+    fn from_vertices(vertices: Vec<[u32; 2]>) -> Option<Self> {
+        if vertices.is_empty() {
+            return None;
+        }
+        Some(Self {
+            indices: generate_indices(vertices.len()),
+            vertices,
+        })
+    }
 }
 
-pub fn build_chunk_mesh(chunks_refs: &ChunksRefs, lod: Lod) -> Option<ChunkMesh> {
+///! the meshes of a chunk, None where there is nothing to draw
+#[derive(Default)]
+pub struct ChunkMeshes {
+    pub opaque: Option<ChunkMesh>,
+    ///! see-through blocks, drawn blended after the opaque ones
+    pub transparent: Option<ChunkMesh>,
+}
+
+pub fn build_chunk_mesh(
+    chunks_refs: &ChunksRefs,
+    lod: Lod,
+    registry: &BlockRegistry,
+) -> ChunkMeshes {
     // early exit, if all faces are culled
     if chunks_refs.is_all_voxels_same() {
-        return None;
+        return ChunkMeshes::default();
     }
-    let mut mesh = ChunkMesh::default();
 
-    // solid binary for each x,y,z axis (3)
-    let mut axis_cols = [[[0u64; CHUNK_SIZE_P]; CHUNK_SIZE_P]; 3];
+    // voxels per axis at this level of detail
+    let size = lod.size() as usize;
+    let sampler = if lod == Lod::L32 {
+        VoxelSampler::Full(chunks_refs)
+    } else {
+        VoxelSampler::Downsampled(Downsampled::new(chunks_refs, lod, registry))
+    };
+
+    // binary for each x,y,z axis (3), of opaque (0) and see-through (1) blocks
+    let mut axis_cols = [[[[0u64; CHUNK_SIZE_P]; CHUNK_SIZE_P]; 3]; 2];
 
     // the cull mask to perform greedy slicing, based on solids on previous axis_cols
-    let mut col_face_masks = [[[0u64; CHUNK_SIZE_P]; CHUNK_SIZE_P]; 6];
+    let mut col_face_masks = [[[[0u64; CHUNK_SIZE_P]; CHUNK_SIZE_P]; 6]; 2];
 
     #[inline]
     fn add_voxel_to_axis_cols(
@@ -41,79 +79,158 @@ pub fn build_chunk_mesh(chunks_refs: &ChunksRefs, lod: Lod) -> Option<ChunkMesh>
         x: usize,
         y: usize,
         z: usize,
-        axis_cols: &mut [[[u64; 34]; 34]; 3],
+        axis_cols: &mut [[[[u64; 34]; 34]; 3]; 2],
+        registry: &BlockRegistry,
     ) {
-        if b.block_type.is_solid() {
-            // x,z - y axis
-            axis_cols[0][z][x] |= 1u64 << y as u64;
-            // z,y - x axis
-            axis_cols[1][y][z] |= 1u64 << x as u64;
-            // x,y - z axis
-            axis_cols[2][y][x] |= 1u64 << z as u64;
-        }
+        let axis_cols = if registry.is_opaque(b.block_type) {
+            &mut axis_cols[0]
+        } else if registry.is_translucent(b.block_type) {
+            &mut axis_cols[1]
+        } else {
+            return;
+        };
+        // x,z - y axis
+        axis_cols[0][z][x] |= 1u64 << y as u64;
+        // z,y - x axis
+        axis_cols[1][y][z] |= 1u64 << x as u64;
+        // x,y - z axis
+        axis_cols[2][y][x] |= 1u64 << z as u64;
     }
 
-    // inner chunk voxels.
-    let chunk = &*chunks_refs.chunks[vec3_to_index(IVec3::new(1, 1, 1), 3)];
-    assert!(chunk.voxels.len() == CHUNK_SIZE3 || chunk.voxels.len() == 1);
-    for z in 0..CHUNK_SIZE {
-        for y in 0..CHUNK_SIZE {
-            for x in 0..CHUNK_SIZE {
-                let i = match chunk.voxels.len() {
-                    1 => 0,
-                    _ => (z * CHUNK_SIZE + y) * CHUNK_SIZE + x,
-                };
-                add_voxel_to_axis_cols(&chunk.voxels[i], x + 1, y + 1, z + 1, &mut axis_cols)
+    match &sampler {
+        VoxelSampler::Full(_) => {
+            // inner chunk voxels.
+            let chunk = &*chunks_refs.chunks[vec3_to_index(IVec3::new(1, 1, 1), 3)];
+            for z in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    for x in 0..CHUNK_SIZE {
+                        let i = (z * CHUNK_SIZE + y) * CHUNK_SIZE + x;
+                        add_voxel_to_axis_cols(
+                            chunk.get_block(i),
+                            x + 1,
+                            y + 1,
+                            z + 1,
+                            &mut axis_cols,
+                            registry,
+                        )
+                    }
+                }
             }
-        }
-    }
 
-    // neighbor chunk voxels.
-    // note(leddoo): couldn't be bothered to optimize these.
-    //  might be worth it though. together, they take
-    //  almost as long as the entire "inner chunk" loop.
-    for z in [0, CHUNK_SIZE_P - 1] {
-        for y in 0..CHUNK_SIZE_P {
-            for x in 0..CHUNK_SIZE_P {
-                let pos = ivec3(x as i32, y as i32, z as i32) - IVec3::ONE;
-                add_voxel_to_axis_cols(chunks_refs.get_block(pos), x, y, z, &mut axis_cols);
+            // neighbor chunk voxels.
+            // note(leddoo): couldn't be bothered to optimize these.
+            //  might be worth it though. together, they take
+            //  almost as long as the entire "inner chunk" loop.
+            for z in [0, CHUNK_SIZE_P - 1] {
+                for y in 0..CHUNK_SIZE_P {
+                    for x in 0..CHUNK_SIZE_P {
+                        let pos = ivec3(x as i32, y as i32, z as i32) - IVec3::ONE;
+                        add_voxel_to_axis_cols(
+                            chunks_refs.get_block(pos),
+                            x,
+                            y,
+                            z,
+                            &mut axis_cols,
+                            registry,
+                        );
+                    }
+                }
             }
-        }
-    }
-    for z in 0..CHUNK_SIZE_P {
-        for y in [0, CHUNK_SIZE_P - 1] {
-            for x in 0..CHUNK_SIZE_P {
-                let pos = ivec3(x as i32, y as i32, z as i32) - IVec3::ONE;
-                add_voxel_to_axis_cols(chunks_refs.get_block(pos), x, y, z, &mut axis_cols);
+            for z in 0..CHUNK_SIZE_P {
+                for y in [0, CHUNK_SIZE_P - 1] {
+                    for x in 0..CHUNK_SIZE_P {
+                        let pos = ivec3(x as i32, y as i32, z as i32) - IVec3::ONE;
+                        add_voxel_to_axis_cols(
+                            chunks_refs.get_block(pos),
+                            x,
+                            y,
+                            z,
+                            &mut axis_cols,
+                            registry,
+                        );
+                    }
+                }
+            }
+            for z in 0..CHUNK_SIZE_P {
+                for x in [0, CHUNK_SIZE_P - 1] {
+                    for y in 0..CHUNK_SIZE_P {
+                        let pos = ivec3(x as i32, y as i32, z as i32) - IVec3::ONE;
+                        add_voxel_to_axis_cols(
+                            chunks_refs.get_block(pos),
+                            x,
+                            y,
+                            z,
+                            &mut axis_cols,
+                            registry,
+                        );
+                    }
+                }
             }
         }
-    }
-    for z in 0..CHUNK_SIZE_P {
-        for x in [0, CHUNK_SIZE_P - 1] {
-            for y in 0..CHUNK_SIZE_P {
-                let pos = ivec3(x as i32, y as i32, z as i32) - IVec3::ONE;
-                add_voxel_to_axis_cols(chunks_refs.get_block(pos), x, y, z, &mut axis_cols);
+        VoxelSampler::Downsampled(downsampled) => {
+            // the padding is part of the downsampled voxels
+            for z in 0..size + 2 {
+                for y in 0..size + 2 {
+                    for x in 0..size + 2 {
+                        let pos = ivec3(x as i32, y as i32, z as i32) - IVec3::ONE;
+                        add_voxel_to_axis_cols(
+                            downsampled.get(pos),
+                            x,
+                            y,
+                            z,
+                            &mut axis_cols,
+                            registry,
+                        );
+                    }
+                }
             }
         }
     }
 
     // face culling
+    // opaque faces show against anything see-through, so glass doesn't hide what's behind it
+    // see-through faces are hidden by opaque blocks and other see-through blocks
     for axis in 0..3 {
-        for z in 0..CHUNK_SIZE_P {
-            for x in 0..CHUNK_SIZE_P {
+        for z in 0..size + 2 {
+            for x in 0..size + 2 {
                 // set if current is solid, and next is air
-                let col = axis_cols[axis][z][x];
+                let col = axis_cols[0][axis][z][x];
 
                 // sample descending axis, and set true when air meets solid
-                col_face_masks[2 * axis + 0][z][x] = col & !(col << 1);
+                col_face_masks[0][2 * axis + 0][z][x] = col & !(col << 1);
                 // sample ascending axis, and set true when air meets solid
-                col_face_masks[2 * axis + 1][z][x] = col & !(col >> 1);
+                col_face_masks[0][2 * axis + 1][z][x] = col & !(col >> 1);
+
+                let see_through = axis_cols[1][axis][z][x];
+                let filled = col | see_through;
+                col_face_masks[1][2 * axis + 0][z][x] = see_through & !(filled << 1);
+                col_face_masks[1][2 * axis + 1][z][x] = see_through & !(filled >> 1);
             }
         }
     }
 
+    ChunkMeshes {
+        opaque: ChunkMesh::from_vertices(mesh_faces(&col_face_masks[0], &sampler, lod, registry)),
+        transparent: ChunkMesh::from_vertices(mesh_faces(
+            &col_face_masks[1],
+            &sampler,
+            lod,
+            registry,
+        )),
+    }
+}
+
+///! greedy mesh the faces set in col_face_masks
+fn mesh_faces(
+    col_face_masks: &[[[u64; CHUNK_SIZE_P]; CHUNK_SIZE_P]; 6],
+    sampler: &VoxelSampler,
+    lod: Lod,
+    registry: &BlockRegistry,
+) -> Vec<[u32; 2]> {
+    let size = lod.size() as usize;
+
     // greedy meshing planes for every axis (6)
-    // key(block + ao) -> HashMap<axis(0-32), binary_plane>
+    // key(block + ao + light) -> HashMap<axis(0-32), binary_plane>
     // note(leddoo): don't ask me how this isn't a massive blottleneck.
     //  might become an issue in the future, when there are more block types.
     //  consider using a single hashmap with key (axis, block_hash, y).
@@ -129,15 +246,24 @@ pub fn build_chunk_mesh(chunks_refs: &ChunksRefs, lod: Lod) -> Option<ChunkMesh>
 
     // find faces and build binary planes based on the voxel block+ao etc...
     for axis in 0..6 {
-        for z in 0..CHUNK_SIZE {
-            for x in 0..CHUNK_SIZE {
+        // the voxel in front of the face, its light falls on the face
+        let front = match axis {
+            0 => IVec3::NEG_Y,
+            1 => IVec3::Y,
+            2 => IVec3::NEG_X,
+            3 => IVec3::X,
+            4 => IVec3::NEG_Z,
+            _ => IVec3::Z,
+        };
+        for z in 0..size {
+            for x in 0..size {
                 // skip padded by adding 1(for x padding) and (z+1) for (z padding)
                 let mut col = col_face_masks[axis][z + 1][x + 1];
 
                 // removes the right most padding value, because it's invalid
                 col >>= 1;
                 // removes the left most padding value, because it's invalid
-                col &= !(1 << CHUNK_SIZE as u64);
+                col &= !(1 << size as u64);
 
                 while col != 0 {
                     let y = col.trailing_zeros();
@@ -164,16 +290,18 @@ pub fn build_chunk_mesh(chunks_refs: &ChunksRefs, lod: Lod) -> Option<ChunkMesh>
                             _ => ivec3(ao_offset.x, ao_offset.y, 1),  // back
                         };
                         let ao_voxel_pos = voxel_pos + ao_sample_offset;
-                        let ao_block = chunks_refs.get_block(ao_voxel_pos);
-                        if ao_block.block_type.is_solid() {
+                        let ao_block = sampler.get(ao_voxel_pos);
+                        if registry.is_opaque(ao_block.block_type) {
                             ao_index |= 1u32 << ao_i;
                         }
                     }
 
-                    let current_voxel = chunks_refs.get_block_no_neighbour(voxel_pos);
+                    let current_voxel = sampler.get(voxel_pos);
                     // let current_voxel = chunks_refs.get_block(voxel_pos);
-                    // we can only greedy mesh same block types + same ambient occlusion
-                    let block_hash = ao_index | ((current_voxel.block_type as u32) << 9);
+                    let light = sampler.light(voxel_pos + front) as u32;
+                    // we can only greedy mesh same block types + same ambient occlusion + same light
+                    let block_hash =
+                        ao_index | ((current_voxel.block_type.id() as u32) << 9) | (light << 16);
                     let data = data[axis]
                         .entry(block_hash)
                         .or_default()
@@ -197,24 +325,156 @@ pub fn build_chunk_mesh(chunks_refs: &ChunksRefs, lod: Lod) -> Option<ChunkMesh>
         };
         for (block_ao, axis_plane) in block_ao_data.into_iter() {
             let ao = block_ao & 0b111111111;
-            let block_type = block_ao >> 9;
+            let block_type = (block_ao >> 9) & 0b1111111;
+            let light = block_ao >> 16;
+            let layer = registry.face_layer(BlockType(block_type as u16), facedir);
             for (axis_pos, plane) in axis_plane.into_iter() {
                 let quads_from_axis = greedy_mesh_binary_plane(plane, lod.size() as u32);
 
                 quads_from_axis.into_iter().for_each(|q| {
-                    q.append_vertices(&mut vertices, facedir, axis_pos, &Lod::L32, ao, block_type)
+                    q.append_vertices(
+                        &mut vertices,
+                        facedir,
+                        axis_pos,
+                        &lod,
+                        ao,
+                        block_type,
+                        layer,
+                        light,
+                    )
                 });
             }
         }
     }
 
-    mesh.vertices.extend(vertices);
-    if mesh.vertices.is_empty() {
-        None
-    } else {
-        mesh.indices = generate_indices(mesh.vertices.len());
-        Some(mesh)
+    vertices
+}
+
+///! where the mesher reads voxels from, positions are local to the middle chunk
+///! and may be one voxel outside of it
+enum VoxelSampler<'a> {
+    Full(&'a ChunksRefs),
+    Downsampled(Downsampled),
+}
+
+impl VoxelSampler<'_> {
+    #[inline]
+    fn get(&self, pos: IVec3) -> &BlockData {
+        match self {
+            VoxelSampler::Full(chunks_refs) => chunks_refs.get_block(pos),
+            VoxelSampler::Downsampled(downsampled) => downsampled.get(pos),
+        }
     }
+
+    ///! packed sky and block light
+    #[inline]
+    fn light(&self, pos: IVec3) -> u8 {
+        match self {
+            VoxelSampler::Full(chunks_refs) => chunks_refs.get_light(pos),
+            VoxelSampler::Downsampled(downsampled) => downsampled.light(pos),
+        }
+    }
+}
+
+///! the middle chunk at a lower resolution
+///! a coarse voxel is solid if any voxel it covers is solid, so distant terrain never shrinks,
+///! and takes the block type of its highest solid voxel, so grass stays on top.
+///! the one voxel border is the neighbours downsampled the same way, so buried chunks stay empty.
+///! a coarse voxel's light is the brightest of the voxels it covers, border included.
+struct Downsampled {
+    size: i32,
+    voxels: Vec<BlockData>,
+    lights: Vec<u8>,
+}
+
+impl Downsampled {
+    fn new(chunks_refs: &ChunksRefs, lod: Lod, registry: &BlockRegistry) -> Self {
+        let size = lod.size();
+        let jump = lod.jump_index();
+        let padded = (size + 2) as usize;
+        let mut voxels = vec![BlockData::default(); padded * padded * padded];
+
+        for z in -1..=size {
+            for y in -1..=size {
+                for x in -1..=size {
+                    // border voxels come from the neighbours, downsampled the same way
+                    let origin = ivec3(x, y, z) * jump;
+                    let chunk_offset = origin.div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+                    let chunk = &*chunks_refs.chunks[vec3_to_index(chunk_offset + IVec3::ONE, 3)];
+                    let origin = origin.rem_euclid(IVec3::splat(CHUNK_SIZE as i32));
+                    let Some(block) = coarse_voxel(chunk, origin, jump, registry) else {
+                        continue;
+                    };
+                    let i = vec3_to_index(ivec3(x, y, z) + IVec3::ONE, size + 2);
+                    voxels[i] = block;
+                }
+            }
+        }
+
+        let mut lights = vec![0u8; padded * padded * padded];
+        for z in -1..=size {
+            for y in -1..=size {
+                for x in -1..=size {
+                    let origin = ivec3(x, y, z) * jump;
+                    let (mut sky, mut block) = (0, 0);
+                    for i in 0..jump * jump * jump {
+                        let light = chunks_refs.get_light(origin + index_to_ivec3_bounds(i, jump));
+                        sky = sky.max(light >> 4);
+                        block = block.max(light & 0xf);
+                    }
+                    lights[vec3_to_index(ivec3(x, y, z) + IVec3::ONE, size + 2)] = sky << 4 | block;
+                }
+            }
+        }
+        Self {
+            size,
+            voxels,
+            lights,
+        }
+    }
+
+    ///! pos goes from -1 to size included
+    #[inline]
+    fn get(&self, pos: IVec3) -> &BlockData {
+        &self.voxels[vec3_to_index(pos + IVec3::ONE, self.size + 2)]
+    }
+
+    #[inline]
+    fn light(&self, pos: IVec3) -> u8 {
+        self.lights[vec3_to_index(pos + IVec3::ONE, self.size + 2)]
+    }
+}
+
+///! highest opaque voxel of the jump sized cube starting at origin,
+///! or its highest see-through voxel if there is no opaque one, None if all air
+fn coarse_voxel(
+    chunk: &ChunkData,
+    origin: IVec3,
+    jump: i32,
+    registry: &BlockRegistry,
+) -> Option<BlockData> {
+    let visible = |block: &BlockData| {
+        registry.is_opaque(block.block_type) || registry.is_translucent(block.block_type)
+    };
+    if let Some(block) = chunk.get_block_if_filled() {
+        return visible(block).then_some(*block);
+    }
+    let mut see_through = None;
+    for y in (0..jump).rev() {
+        for z in 0..jump {
+            for x in 0..jump {
+                let i = vec3_to_index(origin + ivec3(x, y, z), CHUNK_SIZE as i32);
+                let block = chunk.get_block(i);
+                if registry.is_opaque(block.block_type) {
+                    return Some(*block);
+                }
+                if see_through.is_none() && visible(block) {
+                    see_through = Some(*block);
+                }
+            }
+        }
+    }
+    see_through
 }
 
 // todo: compress further?
@@ -230,12 +490,14 @@ impl GreedyQuad {
     ///! compress this quad data into the input vertices vec
     pub fn append_vertices(
         &self,
-        vertices: &mut Vec<u32>,
+        vertices: &mut Vec<[u32; 2]>,
         face_dir: FaceDir,
         axis: u32,
         lod: &Lod,
         ao: u32,
         block_type: u32,
+        layer: u32,
+        light: u32,
     ) {
         // let negate_axis = face_dir.negate_axis();
         // let axis = axis as i32 + negate_axis;
@@ -248,6 +510,18 @@ impl GreedyQuad {
         let v3ao = ((ao >> 5) & 1) + ((ao >> 8) & 1) + ((ao >> 7) & 1);
         let v4ao = ((ao >> 1) & 1) + ((ao >> 2) & 1) + ((ao >> 5) & 1);
 
+        // uvs span the quad in blocks, the sampler repeats the texture
+        // on side faces the quad's y is the world's up, flip it so textures stand upright
+        let (w, h) = (self.w * jump as u32, self.h * jump as u32);
+        let (v_bottom, v_top) = match face_dir {
+            FaceDir::Up | FaceDir::Down => (0, h),
+            _ => (h, 0),
+        };
+        let uv1 = make_vertex_uv_u32(uvec2(0, v_bottom), layer, light);
+        let uv2 = make_vertex_uv_u32(uvec2(w, v_bottom), layer, light);
+        let uv3 = make_vertex_uv_u32(uvec2(w, v_top), layer, light);
+        let uv4 = make_vertex_uv_u32(uvec2(0, v_top), layer, light);
+
         let v1 = make_vertex_u32(
             face_dir.world_to_sample(axis as i32, self.x as i32, self.y as i32, &lod) * jump,
             v1ao,
@@ -289,7 +563,7 @@ impl GreedyQuad {
         );
 
         // the quad vertices to be added
-        let mut new_vertices = VecDeque::from([v1, v2, v3, v4]);
+        let mut new_vertices = VecDeque::from([[v1, uv1], [v2, uv2], [v3, uv3], [v4, uv4]]);
 
         // triangle vertex order is different depending on the facing direction
         // due to indices always being the same
@@ -311,7 +585,7 @@ impl GreedyQuad {
 }
 
 ///! generate quads of a binary slice
-///! lod not implemented atm
+///! only the first lod_size rows and bits are meshed
 pub fn greedy_mesh_binary_plane(mut data: [u32; 32], lod_size: u32) -> Vec<GreedyQuad> {
     let mut greedy_quads = vec![];
     for row in 0..data.len() {
@@ -353,3 +627,36 @@ pub fn greedy_mesh_binary_plane(mut data: [u32; 32], lod_size: u32) -> Vec<Greed
     }
     greedy_quads
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::environment::light::ChunkLight;
+
+    #[test]
+    fn buried_chunks_have_no_faces_at_any_lod() {
+        // a grass chunk inside dirt, so the mesher doesn't skip it as all the same
+        let chunks = (0..3 * 3 * 3)
+            .map(|i| {
+                let block_type = if i == 13 {
+                    BlockType::GRASS
+                } else {
+                    BlockType::DIRT
+                };
+                Arc::new(ChunkData::filled(block_type))
+            })
+            .collect();
+        let lights = (0..3 * 3 * 3)
+            .map(|_| Arc::new(ChunkLight::filled(0, 0)))
+            .collect();
+        let chunks_refs = ChunksRefs { chunks, lights };
+        let registry = BlockRegistry::default();
+        for lod in [Lod::L32, Lod::L16, Lod::L8, Lod::L4, Lod::L2] {
+            let meshes = build_chunk_mesh(&chunks_refs, lod, &registry);
+            assert!(meshes.opaque.is_none(), "{lod:?} has faces");
+            assert!(meshes.transparent.is_none(), "{lod:?} has faces");
+        }
+    }
+}
@@ -1,10 +1,23 @@
 pub mod block;
+pub mod block_registry;
 pub mod chunk;
+pub mod collision;
+pub mod culling;
+pub mod diagnostics;
 pub mod engine;
+pub mod export;
 pub mod face_direction;
+pub mod generator;
+pub mod light;
 pub mod mesher;
+pub mod noise;
+pub mod palette;
 pub mod plugin;
 pub mod quad;
+pub mod raycast;
+pub mod region;
 pub mod rendering;
 pub mod scanner;
+pub mod time_of_day;
 pub mod utils;
+pub mod voxel_world;
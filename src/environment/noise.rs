@@ -0,0 +1,75 @@
+use rand::{Rng, SeedableRng, seq::SliceRandom};
+use rand_chacha::ChaCha8Rng;
+
+///! seeded 2d value noise
+///! the permutation and lattice values are drawn once from a chacha stream,
+///! so the same seed always gives the same terrain, on every thread
+pub struct ValueNoise {
+    perm: [u8; 512],
+    values: [f32; 256],
+}
+
+impl ValueNoise {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let mut table: Vec<u8> = (0..=255).collect();
+        table.shuffle(&mut rng);
+
+        let mut perm = [0u8; 512];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = table[i & 255];
+        }
+
+        let mut values = [0f32; 256];
+        for v in values.iter_mut() {
+            *v = rng.random_range(-1.0..1.0);
+        }
+
+        Self { perm, values }
+    }
+
+    ///! pseudo random value in [-1, 1] for an integer lattice point
+    #[inline]
+    fn lattice(&self, x: i32, z: i32) -> f32 {
+        let i = self.perm[(x & 255) as usize] as usize;
+        self.values[self.perm[i + (z & 255) as usize] as usize]
+    }
+
+    ///! smoothly interpolated noise in [-1, 1]
+    pub fn sample(&self, x: f32, z: f32) -> f32 {
+        let x0 = x.floor();
+        let z0 = z.floor();
+        let (ix, iz) = (x0 as i32, z0 as i32);
+
+        // smoothstep the fractional part to hide the lattice
+        let fade = |t: f32| t * t * (3.0 - 2.0 * t);
+        let tx = fade(x - x0);
+        let tz = fade(z - z0);
+
+        let a = self.lattice(ix, iz);
+        let b = self.lattice(ix + 1, iz);
+        let c = self.lattice(ix, iz + 1);
+        let d = self.lattice(ix + 1, iz + 1);
+
+        let top = a + (b - a) * tx;
+        let bottom = c + (d - c) * tx;
+        top + (bottom - top) * tz
+    }
+
+    ///! fractal brownian motion, each octave doubles frequency and halves amplitude
+    ///! result is normalized back to [-1, 1]
+    pub fn fbm(&self, x: f32, z: f32, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max = 0.0;
+        for _ in 0..octaves {
+            sum += self.sample(x * frequency, z * frequency) * amplitude;
+            max += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        sum / max
+    }
+}
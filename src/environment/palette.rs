@@ -0,0 +1,107 @@
+///! fixed length array of small unsigned integers, bit packed into u64 words
+///! entries are 0, 1, 2, 4, 8 or 16 bits wide so they never straddle two words
+#[derive(Clone, Default)]
+pub struct PackedIndices {
+    bits: u32,
+    words: Vec<u64>,
+}
+
+impl PackedIndices {
+    ///! len entries of the given width, all zero
+    pub fn new(len: usize, bits: u32) -> Self {
+        let word_count = Self::word_count(len, bits).expect("unsupported index width");
+        Self {
+            bits,
+            words: vec![0u64; word_count],
+        }
+    }
+
+    ///! len entries read back from words, None if the width isn't supported or the words don't fit
+    pub fn from_words(len: usize, bits: u32, words: Vec<u64>) -> Option<Self> {
+        (words.len() == Self::word_count(len, bits)?).then_some(Self { bits, words })
+    }
+
+    ///! words holding len entries of the given width, None if the width isn't supported
+    pub fn word_count(len: usize, bits: u32) -> Option<usize> {
+        match bits {
+            0 => Some(0),
+            1 | 2 | 4 | 8 | 16 => Some(len.div_ceil((64 / bits) as usize)),
+            _ => None,
+        }
+    }
+
+    ///! smallest supported width able to index palette_len entries
+    pub fn bits_for(palette_len: usize) -> u32 {
+        match palette_len {
+            0..=1 => 0,
+            2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            17..=256 => 8,
+            _ => 16,
+        }
+    }
+
+    #[inline]
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    ///! the largest value an entry can hold
+    #[inline]
+    pub fn max_value(&self) -> usize {
+        (1usize << self.bits) - 1
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = (64 / self.bits) as usize;
+        let word = self.words[index / per_word];
+        let shift = (index % per_word) as u32 * self.bits;
+        ((word >> shift) & self.mask()) as usize
+    }
+
+    #[inline]
+    pub fn set(&mut self, index: usize, value: usize) {
+        debug_assert!(value <= self.max_value());
+        // zero wide entries can only hold the 0 they already are
+        if self.bits == 0 {
+            return;
+        }
+        let per_word = (64 / self.bits) as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = self.mask();
+        let word = &mut self.words[index / per_word];
+        *word &= !(mask << shift);
+        *word |= (value as u64) << shift;
+    }
+
+    ///! copy of len entries with a new width, bits must be able to hold every entry
+    pub fn repacked(&self, len: usize, bits: u32) -> Self {
+        let mut repacked = Self::new(len, bits);
+        if bits != 0 {
+            for i in 0..len {
+                repacked.set(i, self.get(i));
+            }
+        }
+        repacked
+    }
+
+    ///! the packed entries, for serializing them
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    ///! heap memory used by the entries
+    pub fn byte_size(&self) -> usize {
+        self.words.len() * size_of::<u64>()
+    }
+
+    #[inline]
+    fn mask(&self) -> u64 {
+        (1u64 << self.bits) - 1
+    }
+}
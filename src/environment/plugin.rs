@@ -1,16 +1,57 @@
-use crate::environment::engine::*;
+use crate::environment::{
+    block_registry::{
+        BlockDefinitions, BlockDefinitionsLoader, BlockRegistry, apply_block_definitions,
+        load_block_definitions,
+    },
+    culling::cave_culling,
+    diagnostics::EngineDiagnosticsPlugin,
+    engine::*,
+    generator::{WorldGenerators, select_world_generator},
+    voxel_world::BlockChanged,
+};
 use bevy::prelude::*;
 
+///! world data: generation, persistence, edits and light, runs without a GPU
 pub struct EnvironmentPlugin;
 
 impl Plugin for EnvironmentPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Engine::default());
-        app.add_systems(PostUpdate, (start_data_tasks, start_mesh_tasks));
+        app.add_plugins(EngineDiagnosticsPlugin);
+        app.init_resource::<WorldGenerators>();
+        app.init_asset::<BlockDefinitions>();
+        app.init_asset_loader::<BlockDefinitionsLoader>();
+        app.init_resource::<BlockRegistry>();
+        app.add_event::<BlockChanged>();
+        app.add_systems(
+            Startup,
+            (
+                select_world_generator,
+                select_world_storage,
+                load_block_definitions,
+            ),
+        );
+        app.add_systems(Update, apply_block_definitions);
+        app.add_systems(PostUpdate, start_data_tasks);
         app.add_systems(Update, start_modifications);
+        app.add_systems(Update, (join_data, light_chunks, unload_data).chain());
+        app.add_systems(Last, save_world_on_exit);
+    }
+}
+
+///! turns the chunks around scanners into meshes, needs the chunk materials of the renderer
+pub struct ChunkMeshPlugin;
+
+impl Plugin for ChunkMeshPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, start_mesh_tasks);
         app.add_systems(
             Update,
-            ((join_data, join_mesh), (unload_data, unload_mesh)).chain(),
+            (
+                join_mesh.before(light_chunks),
+                unload_mesh.after(light_chunks),
+                cave_culling.after(join_mesh),
+            ),
         );
     }
 }
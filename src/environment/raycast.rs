@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+
+use super::{
+    block::BlockData,
+    block_registry::BlockRegistry,
+    chunk::CHUNK_SIZE_I32,
+    engine::Engine,
+    utils::{vec3_to_index, world_to_chunk},
+};
+
+///! the first solid voxel along a ray
+#[derive(Copy, Clone, Debug)]
+pub struct VoxelRayHit {
+    ///! world position of the voxel that was hit
+    pub block_pos: IVec3,
+    ///! normal of the face the ray entered through, zero if the ray started inside the voxel
+    pub normal: IVec3,
+    ///! distance from the ray origin to the hit point
+    pub distance: f32,
+    pub block: BlockData,
+}
+
+impl VoxelRayHit {
+    ///! where the ray touched the voxel, in world space
+    pub fn point(&self, origin: Vec3, direction: Vec3) -> Vec3 {
+        origin + direction.normalize() * self.distance
+    }
+}
+
+impl Engine {
+    ///! block at a world voxel position, None if its chunk is not loaded
+    pub fn get_block(&self, world_pos: IVec3) -> Option<BlockData> {
+        let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+        let chunk_data = self.world_data.get(&chunk_pos)?;
+        Some(*chunk_data.get_block(vec3_to_index(local_pos, CHUNK_SIZE_I32)))
+    }
+
+    ///! march the voxel grid from origin along direction (amanatides & woo dda),
+    ///! returning the first solid voxel closer than max_distance
+    ///! unloaded chunks are treated as air
+    pub fn raycast(
+        &self,
+        registry: &BlockRegistry,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<VoxelRayHit> {
+        let direction = direction.try_normalize()?;
+
+        let mut block_pos = origin.floor().as_ivec3();
+        let step = direction.signum().as_ivec3();
+
+        // distance along the ray to cross one voxel on each axis
+        let t_delta = direction.recip().abs();
+
+        // distance along the ray to the first voxel boundary on each axis
+        let next_boundary = block_pos.as_vec3() + step.max(IVec3::ZERO).as_vec3();
+        let mut t_max = Vec3::select(
+            direction.cmpeq(Vec3::ZERO),
+            Vec3::INFINITY,
+            (next_boundary - origin) / direction,
+        );
+
+        let mut normal = IVec3::ZERO;
+        let mut distance = 0.0;
+        while distance <= max_distance {
+            if let Some(block) = self.get_block(block_pos) {
+                if registry.is_solid(block.block_type) {
+                    return Some(VoxelRayHit {
+                        block_pos,
+                        normal,
+                        distance,
+                        block,
+                    });
+                }
+            }
+
+            // step along the axis with the closest boundary
+            if t_max.x < t_max.y && t_max.x < t_max.z {
+                block_pos.x += step.x;
+                distance = t_max.x;
+                t_max.x += t_delta.x;
+                normal = IVec3::new(-step.x, 0, 0);
+            } else if t_max.y < t_max.z {
+                block_pos.y += step.y;
+                distance = t_max.y;
+                t_max.y += t_delta.y;
+                normal = IVec3::new(0, -step.y, 0);
+            } else {
+                block_pos.z += step.z;
+                distance = t_max.z;
+                t_max.z += t_delta.z;
+                normal = IVec3::new(0, 0, -step.z);
+            }
+        }
+        None
+    }
+}
+
+///! distance along a normalized ray to where it enters the box min..max, and the normal of the face it entered through
+///! zero and a zero normal if the ray starts inside the box
+pub fn ray_aabb(origin: Vec3, direction: Vec3, min: Vec3, max: Vec3) -> Option<(f32, Vec3)> {
+    // a ray running along a slab never crosses it, it's either always between its planes or never
+    let parallel = direction.cmpeq(Vec3::ZERO);
+    let outside = origin.cmplt(min) | origin.cmpgt(max);
+    if (parallel & outside).any() {
+        return None;
+    }
+
+    // distances to the two planes of each slab
+    let t0 = Vec3::select(parallel, Vec3::NEG_INFINITY, (min - origin) / direction);
+    let t1 = Vec3::select(parallel, Vec3::INFINITY, (max - origin) / direction);
+    let t_near = t0.min(t1);
+    let t_far = t0.max(t1);
+
+    let enter = t_near.max_element();
+    let exit = t_far.min_element();
+    if exit < enter.max(0.0) {
+        return None;
+    }
+    if enter <= 0.0 {
+        return Some((0.0, Vec3::ZERO));
+    }
+
+    let axis = if enter == t_near.x {
+        Vec3::X
+    } else if enter == t_near.y {
+        Vec3::Y
+    } else {
+        Vec3::Z
+    };
+    Some((enter, -axis * direction.signum()))
+}
@@ -0,0 +1,226 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use bevy::math::IVec3;
+
+use super::{
+    block::{BlockData, BlockType},
+    chunk::{CHUNK_SIZE3, ChunkData},
+    utils::vec3_to_index,
+};
+
+///! chunks per axis in a region file
+pub const REGION_SIZE: i32 = 8;
+pub const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const REGION_MAGIC: &[u8; 4] = b"GCRG";
+const REGION_VERSION: u32 = 1;
+///! magic, version, then an (offset, length) pair per chunk
+const HEADER_SIZE: u64 = 8 + REGION_VOLUME as u64 * 8;
+
+const CHUNK_FILLED: u8 = 0;
+const CHUNK_RUN_LENGTH: u8 = 1;
+
+///! a world on disk, chunks are grouped by coordinate into region files
+///!
+///! region file layout, little endian:
+///! - magic "GCRG", format version (u32)
+///! - offset table: (offset u32, length u32) per chunk, zero length if the chunk was never saved
+///! - compressed chunk payloads, see encode_chunk
+pub struct WorldStorage {
+    dir: PathBuf,
+    // region files are read from data tasks and written from the main thread
+    lock: Mutex<()>,
+}
+
+impl WorldStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn region_path(&self, region_pos: IVec3) -> PathBuf {
+        self.dir.join("regions").join(format!(
+            "r.{}.{}.{}.region",
+            region_pos.x, region_pos.y, region_pos.z
+        ))
+    }
+
+    ///! read a chunk, None if it was never saved
+    pub fn load_chunk(&self, chunk_pos: IVec3) -> io::Result<Option<ChunkData>> {
+        let (region_pos, index) = split_chunk_pos(chunk_pos);
+        let _guard = self.lock.lock().unwrap();
+
+        let mut file = match File::open(self.region_path(region_pos)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        read_header(&mut file)?;
+
+        let (offset, length) = read_entry(&mut file, index)?;
+        if length == 0 {
+            return Ok(None);
+        }
+        let mut payload = vec![0u8; length as usize];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut payload)?;
+        decode_chunk(&payload).map(Some)
+    }
+
+    ///! write a chunk, reusing its previous slot in the region file when it fits
+    pub fn save_chunk(&self, chunk_pos: IVec3, chunk: &ChunkData) -> io::Result<()> {
+        let (region_pos, index) = split_chunk_pos(chunk_pos);
+        let payload = encode_chunk(chunk);
+        let path = self.region_path(region_pos);
+        let _guard = self.lock.lock().unwrap();
+
+        fs::create_dir_all(path.parent().unwrap())?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        if file.metadata()?.len() < HEADER_SIZE {
+            // fresh region, write an empty offset table
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(REGION_MAGIC)?;
+            file.write_all(&REGION_VERSION.to_le_bytes())?;
+            file.write_all(&vec![0u8; REGION_VOLUME * 8])?;
+        } else {
+            read_header(&mut file)?;
+        }
+
+        let (old_offset, old_length) = read_entry(&mut file, index)?;
+        // note: outgrown slots are left behind, the file only ever grows
+        let offset = if old_length != 0 && payload.len() as u32 <= old_length {
+            old_offset as u64
+        } else {
+            file.seek(SeekFrom::End(0))?
+        };
+
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&payload)?;
+
+        file.seek(SeekFrom::Start(entry_offset(index)))?;
+        file.write_all(&(offset as u32).to_le_bytes())?;
+        file.write_all(&(payload.len() as u32).to_le_bytes())?;
+        Ok(())
+    }
+}
+
+///! region coordinate, and index of the chunk in the region offset table
+fn split_chunk_pos(chunk_pos: IVec3) -> (IVec3, usize) {
+    let region_pos = chunk_pos.div_euclid(IVec3::splat(REGION_SIZE));
+    let local = chunk_pos.rem_euclid(IVec3::splat(REGION_SIZE));
+    (region_pos, vec3_to_index(local, REGION_SIZE))
+}
+
+fn entry_offset(index: usize) -> u64 {
+    8 + index as u64 * 8
+}
+
+fn read_header(file: &mut File) -> io::Result<()> {
+    let mut header = [0u8; 8];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    if &header[0..4] != REGION_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a region file",
+        ));
+    }
+    let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    if version != REGION_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported region version {version}"),
+        ));
+    }
+    Ok(())
+}
+
+fn read_entry(file: &mut File, index: usize) -> io::Result<(u32, u32)> {
+    let mut entry = [0u8; 8];
+    file.seek(SeekFrom::Start(entry_offset(index)))?;
+    file.read_exact(&mut entry)?;
+    Ok((
+        u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+        u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+    ))
+}
+
+///! compress a chunk
+///! filled chunks are a tag and a block id,
+///! other chunks are run length encoded (run u16, block id u16) pairs in voxel index order
+pub fn encode_chunk(chunk: &ChunkData) -> Vec<u8> {
+    if let Some(block) = chunk.get_block_if_filled() {
+        let mut bytes = vec![CHUNK_FILLED];
+        bytes.extend(block.block_type.id().to_le_bytes());
+        return bytes;
+    }
+
+    let mut bytes = vec![CHUNK_RUN_LENGTH];
+    let push_run = |bytes: &mut Vec<u8>, run: u16, block_type: BlockType| {
+        bytes.extend(run.to_le_bytes());
+        bytes.extend(block_type.id().to_le_bytes());
+    };
+
+    let mut current = chunk.get_block(0).block_type;
+    let mut run = 0u16;
+    for i in 0..CHUNK_SIZE3 {
+        let block_type = chunk.get_block(i).block_type;
+        if block_type != current || run == u16::MAX {
+            push_run(&mut bytes, run, current);
+            current = block_type;
+            run = 0;
+        }
+        run += 1;
+    }
+    push_run(&mut bytes, run, current);
+    bytes
+}
+
+pub fn decode_chunk(bytes: &[u8]) -> io::Result<ChunkData> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let read_u16 = |at: usize| -> io::Result<u16> {
+        bytes
+            .get(at..at + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .ok_or_else(|| invalid("truncated chunk"))
+    };
+
+    match bytes.first() {
+        Some(&CHUNK_FILLED) => Ok(ChunkData::filled(BlockType(read_u16(1)?))),
+        Some(&CHUNK_RUN_LENGTH) => {
+            let mut voxels = Vec::with_capacity(CHUNK_SIZE3);
+            let mut at = 1;
+            while at < bytes.len() {
+                let run = read_u16(at)? as usize;
+                let block_type = BlockType(read_u16(at + 2)?);
+                if voxels.len() + run > CHUNK_SIZE3 {
+                    return Err(invalid("chunk has too many voxels"));
+                }
+                voxels.extend(std::iter::repeat_n(BlockData { block_type }, run));
+                at += 4;
+            }
+            if voxels.len() != CHUNK_SIZE3 {
+                return Err(invalid("chunk is missing voxels"));
+            }
+            Ok(ChunkData::from_voxels(voxels))
+        }
+        _ => Err(invalid("unknown chunk encoding")),
+    }
+}
@@ -1,18 +1,34 @@
 use bevy::{
     app::{App, Update},
-    asset::Asset,
+    asset::{Asset, LoadState, RenderAssetUsages},
     ecs::resource::Resource,
+    image::{ImageAddressMode, ImageFilterMode, ImageSampler, ImageSamplerDescriptor},
     pbr::{MaterialPipeline, MaterialPipelineKey, MaterialPlugin},
     prelude::*,
     render::{
         mesh::{MeshVertexAttribute, MeshVertexBufferLayoutRef, VertexFormat},
         render_resource::{
-            AsBindGroup, PolygonMode, RenderPipelineDescriptor, ShaderRef,
-            SpecializedMeshPipelineError,
+            AsBindGroup, Extent3d, PolygonMode, RenderPipelineDescriptor, ShaderRef,
+            SpecializedMeshPipelineError, TextureDimension, TextureFormat, TextureViewDescriptor,
+            TextureViewDimension,
         },
     },
 };
 
+use super::{
+    block_registry::{BlockRegistry, TextureLayer},
+    chunk::CHUNK_SIZE,
+};
+
+///! width and height of block textures, in pixels
+pub const BLOCK_TEXTURE_SIZE: u32 = 16;
+
+///! origin of transparent chunk meshes inside their chunk, bevy sorts transparent meshes by it
+pub const TRANSPARENT_MESH_ORIGIN: f32 = CHUNK_SIZE as f32 / 2.0;
+
+///! layers whose image is missing or has the wrong size are filled with this, linear rgba
+const MISSING_TEXTURE_COLOR: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+
 // This is the struct that will be passed to your shader
 #[derive(Asset, Reflect, AsBindGroup, Debug, Clone)]
 pub struct ChunkMaterial {
@@ -22,12 +38,21 @@ pub struct ChunkMaterial {
     pub perceptual_roughness: f32,
     #[uniform(0)]
     pub metallic: f32,
+    ///! added to the vertex positions, see TRANSPARENT_MESH_ORIGIN
+    #[uniform(0)]
+    pub origin_offset: f32,
+    ///! one layer per block face texture, see BlockRegistry::face_layer
+    #[texture(1, dimension = "2d_array")]
+    #[sampler(2)]
+    pub textures: Option<Handle<Image>>,
+    ///! Opaque for the solid pass, Blend for transparent blocks
+    pub alpha_mode: AlphaMode,
 }
 
 // A "high" random id should be used for custom attributes to ensure consistent sorting and avoid collisions with other attributes.
 // See the MeshVertexAttribute docs for more info.
 pub const ATTRIBUTE_VOXEL: MeshVertexAttribute =
-    MeshVertexAttribute::new("Voxel", 988540919, VertexFormat::Uint32);
+    MeshVertexAttribute::new("Voxel", 988540919, VertexFormat::Uint32x2);
 
 #[derive(Resource)]
 pub enum ChunkMaterialWireframeMode {
@@ -42,12 +67,152 @@ impl Plugin for RenderingPlugin {
         app.add_plugins(MaterialPlugin::<ChunkMaterial>::default());
         app.add_plugins(MaterialPlugin::<ChunkMaterialWireframe>::default());
         app.insert_resource(ChunkMaterialWireframeMode::Off);
+        app.init_resource::<BlockTextureArray>();
         app.add_systems(Update, apply_chunk_material);
+        app.add_systems(
+            Update,
+            (load_block_textures, build_block_texture_array).chain(),
+        );
+    }
+}
+
+///! the texture array bound to chunk materials, rebuilt when the block registry changes
+#[derive(Resource, Default)]
+pub struct BlockTextureArray {
+    pub image: Option<Handle<Image>>,
+    layers: Vec<TextureLayer>,
+    ///! images of the layers being loaded, None for color layers
+    pending: Option<Vec<Option<Handle<Image>>>>,
+}
+
+fn load_block_textures(
+    registry: Res<BlockRegistry>,
+    asset_server: Res<AssetServer>,
+    mut textures: ResMut<BlockTextureArray>,
+) {
+    if !registry.is_changed() {
+        return;
     }
+    textures.layers = registry.texture_layers().to_vec();
+    let pending = textures
+        .layers
+        .iter()
+        .map(|layer| match layer {
+            TextureLayer::Image(path) => Some(asset_server.load(path.clone())),
+            TextureLayer::Color(_) => None,
+        })
+        .collect();
+    textures.pending = Some(pending);
+}
+
+///! once every layer image is loaded (or failed to), stack them into one array texture
+fn build_block_texture_array(
+    mut textures: ResMut<BlockTextureArray>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut chunk_materials: ResMut<Assets<ChunkMaterial>>,
+    mut wireframe_materials: ResMut<Assets<ChunkMaterialWireframe>>,
+) {
+    let Some(pending) = &textures.pending else {
+        return;
+    };
+    let loading = pending.iter().flatten().any(|handle| {
+        matches!(
+            asset_server.get_load_state(handle.id()),
+            Some(LoadState::NotLoaded | LoadState::Loading)
+        )
+    });
+    if loading {
+        return;
+    }
+
+    let mut data = vec![];
+    for (layer, handle) in textures.layers.iter().zip(pending) {
+        let pixels = match layer {
+            TextureLayer::Image(path) => {
+                let pixels = handle
+                    .as_ref()
+                    .and_then(|handle| images.get(handle))
+                    .and_then(layer_pixels);
+                if pixels.is_none() {
+                    warn!(
+                        "block texture {path} is missing or not {BLOCK_TEXTURE_SIZE}x{BLOCK_TEXTURE_SIZE}"
+                    );
+                }
+                pixels.unwrap_or_else(|| color_pixels(MISSING_TEXTURE_COLOR))
+            }
+            TextureLayer::Color(color) => color_pixels(*color),
+        };
+        data.extend(pixels);
+    }
+    // an array texture needs at least one layer
+    let layer_count = textures.layers.len().max(1) as u32;
+    if data.is_empty() {
+        data = color_pixels([1.0; 4]);
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            width: BLOCK_TEXTURE_SIZE,
+            height: BLOCK_TEXTURE_SIZE,
+            depth_or_array_layers: layer_count,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    // a single layer would be viewed as a plain 2d texture otherwise
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..default()
+    });
+    // greedy quads span several blocks, their uvs go past 1 and repeat the texture
+    image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        mag_filter: ImageFilterMode::Nearest,
+        min_filter: ImageFilterMode::Nearest,
+        ..default()
+    });
+
+    let handle = images.add(image);
+    for (_, material) in chunk_materials.iter_mut() {
+        material.textures = Some(handle.clone());
+    }
+    for (_, material) in wireframe_materials.iter_mut() {
+        material.textures = Some(handle.clone());
+    }
+    info!("built block texture array with {layer_count} layers");
+    textures.image = Some(handle);
+    textures.pending = None;
+}
+
+///! rgba8 srgb pixels of a block texture image, None if it has the wrong size
+fn layer_pixels(image: &Image) -> Option<Vec<u8>> {
+    if image.width() != BLOCK_TEXTURE_SIZE || image.height() != BLOCK_TEXTURE_SIZE {
+        return None;
+    }
+    if image.texture_descriptor.format == TextureFormat::Rgba8UnormSrgb {
+        return image.data.clone();
+    }
+    image.convert(TextureFormat::Rgba8UnormSrgb)?.data
+}
+
+fn color_pixels(color: [f32; 4]) -> Vec<u8> {
+    let [r, g, b, a] = color;
+    let srgba = Srgba::from(LinearRgba::new(r, g, b, a)).to_u8_array();
+    srgba.repeat((BLOCK_TEXTURE_SIZE * BLOCK_TEXTURE_SIZE) as usize)
 }
 
 fn apply_chunk_material(
-    no_wireframe: Query<Entity, With<MeshMaterial3d<ChunkMaterial>>>,
+    no_wireframe: Query<
+        Entity,
+        (
+            With<MeshMaterial3d<ChunkMaterial>>,
+            Without<TransparentChunkMesh>,
+        ),
+    >,
     wireframe: Query<Entity, With<MeshMaterial3d<ChunkMaterialWireframe>>>,
     input: Res<ButtonInput<KeyCode>>,
     mut mode: ResMut<ChunkMaterialWireframeMode>,
@@ -86,6 +251,13 @@ fn apply_chunk_material(
 #[derive(Resource, Reflect)]
 pub struct GlobalChunkMaterial(pub MeshMaterial3d<ChunkMaterial>);
 #[derive(Resource, Reflect)]
+pub struct GlobalChunkTransparentMaterial(pub MeshMaterial3d<ChunkMaterial>);
+
+///! the see-through part of a chunk, child of the chunk entity
+///! keeps its material when wireframe is toggled
+#[derive(Component)]
+pub struct TransparentChunkMesh;
+#[derive(Resource, Reflect)]
 pub struct GlobalChunkWireframeMaterial(pub MeshMaterial3d<ChunkMaterialWireframe>);
 
 impl Material for ChunkMaterial {
@@ -97,7 +269,7 @@ impl Material for ChunkMaterial {
     }
 
     fn alpha_mode(&self) -> AlphaMode {
-        AlphaMode::Opaque
+        self.alpha_mode
     }
 
     fn specialize(
@@ -130,6 +302,11 @@ pub struct ChunkMaterialWireframe {
     pub perceptual_roughness: f32,
     #[uniform(0)]
     pub metallic: f32,
+    #[uniform(0)]
+    pub origin_offset: f32,
+    #[texture(1, dimension = "2d_array")]
+    #[sampler(2)]
+    pub textures: Option<Handle<Image>>,
 }
 
 impl Material for ChunkMaterialWireframe {
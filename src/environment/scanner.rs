@@ -1,8 +1,14 @@
 use std::collections::VecDeque;
 
-use bevy::{platform::collections::HashSet, prelude::*};
+use bevy::{
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
 
-use super::{engine::Engine, utils::index_to_ivec3_bounds};
+use super::{
+    engine::{Engine, MAX_DATA_TASKS},
+    utils::{cli_arg, index_to_ivec3_bounds},
+};
 
 pub const ADJACENT_CHUNK_DIRECTIONS: [IVec3; 27] = [
     IVec3 { x: 0, y: 0, z: 0 },
@@ -52,15 +58,30 @@ pub const ADJACENT_AO_DIRS: [IVec2; 9] = [
     ivec2(1, 1),
 ];
 
-pub const MAX_DATA_TASKS: usize = 9;
-pub const MAX_MESH_TASKS: usize = 3;
-
 pub const MAX_SCANS: usize = 26000;
 
+///! render distance used when `--render-distance <chunks>` isn't given
+pub const DEFAULT_RENDER_DISTANCE: i32 = 6;
+
+///! radius, in chunks, of the area scanners keep meshed around them
+#[derive(Resource, Copy, Clone, Debug)]
+pub struct RenderDistance(pub i32);
+
+impl Default for RenderDistance {
+    fn default() -> Self {
+        let distance = cli_arg("--render-distance")
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(DEFAULT_RENDER_DISTANCE);
+        RenderDistance(distance.max(1))
+    }
+}
+
 pub struct ScannerPlugin;
 
 impl Plugin for ScannerPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<RenderDistance>();
+        app.init_resource::<ScannedAreas>();
         app.add_systems(
             PreUpdate,
             (
@@ -123,49 +144,152 @@ impl Scanner {
             unresolved_mesh_unload: VecDeque::default(),
         }
     }
+
+    ///! keeps the chunk data around it loaded without meshing anything, for headless worlds
+    pub fn data_only(distance: i32) -> Self {
+        Self {
+            mesh_sampling_offsets: Vec::new(),
+            ..Self::new(distance)
+        }
+    }
+}
+
+///! chunk the scanner stands in
+#[inline]
+pub fn scanner_chunk_pos(g_transform: &GlobalTransform) -> IVec3 {
+    ((g_transform.translation() - Vec3::splat(16.0)) * (1.0 / 32.0)).as_ivec3()
+}
+
+///! squared distance from chunk_pos to the closest of scan_positions, 0 without scanners
+#[inline]
+pub fn closest_distance_squared(chunk_pos: IVec3, scan_positions: &[IVec3]) -> i32 {
+    scan_positions
+        .iter()
+        .map(|scan_pos| chunk_pos.distance_squared(*scan_pos))
+        .min()
+        .unwrap_or(0)
+}
+
+///! chunks each scanner currently wants, so they can be released when it moves or despawns
+#[derive(Resource, Default)]
+pub struct ScannedAreas(pub HashMap<Entity, ScannedArea>);
+
+#[derive(Default)]
+pub struct ScannedArea {
+    pub data: HashSet<IVec3>,
+    pub mesh: HashSet<IVec3>,
+}
+
+///! count one more scanner wanting each chunk, returns the chunks nobody wanted before
+fn acquire(refs: &mut HashMap<IVec3, u32>, chunks: impl Iterator<Item = IVec3>) -> Vec<IVec3> {
+    let mut wanted = vec![];
+    for chunk_pos in chunks {
+        let count = refs.entry(chunk_pos).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            wanted.push(chunk_pos);
+        }
+    }
+    wanted
+}
+
+///! count one less scanner wanting each chunk, returns the chunks nobody wants anymore
+fn release(refs: &mut HashMap<IVec3, u32>, chunks: impl Iterator<Item = IVec3>) -> Vec<IVec3> {
+    let mut unwanted = vec![];
+    for chunk_pos in chunks {
+        let Some(count) = refs.get_mut(&chunk_pos) else {
+            continue;
+        };
+        *count -= 1;
+        if *count == 0 {
+            refs.remove(&chunk_pos);
+            unwanted.push(chunk_pos);
+        }
+    }
+    unwanted
 }
 
 ///! on scanner chunk change, enqueue chunks to load/unload
+///! chunks are reference counted, so a chunk is only unloaded once no scanner covers it
 fn detect_move(
-    mut scanners: Query<(&mut Scanner, &GlobalTransform)>,
+    mut scanners: Query<(Entity, &mut Scanner, &GlobalTransform)>,
+    mut removed_scanners: RemovedComponents<Scanner>,
+    mut scanned_areas: ResMut<ScannedAreas>,
     mut voxel_engine: ResMut<Engine>,
 ) {
-    for (mut scanner, g_transform) in scanners.iter_mut() {
-        let chunk_pos = ((g_transform.translation() - Vec3::splat(16.0)) * (1.0 / 32.0)).as_ivec3();
-        let previous_chunk_pos = scanner.prev_chunk_pos;
+    // despawned scanners release their whole area
+    for entity in removed_scanners.read() {
+        let Some(area) = scanned_areas.0.remove(&entity) else {
+            continue;
+        };
+        let data_unload = release(&mut voxel_engine.data_refs, area.data.into_iter());
+        for chunk_pos in data_unload {
+            voxel_engine.load_data_queue.retain(|p| *p != chunk_pos);
+            voxel_engine.cancel_data_task(chunk_pos);
+            if voxel_engine.world_data.contains_key(&chunk_pos) {
+                voxel_engine.unload_data_queue.push(chunk_pos);
+            }
+        }
+        let mesh_unload = release(&mut voxel_engine.mesh_refs, area.mesh.into_iter());
+        for chunk_pos in mesh_unload {
+            voxel_engine.load_mesh_queue.retain(|p| *p != chunk_pos);
+            voxel_engine.cancel_mesh_task(chunk_pos);
+            voxel_engine.unload_mesh_queue.push(chunk_pos);
+        }
+    }
+
+    let mut any_moved = false;
+    for (entity, mut scanner, g_transform) in scanners.iter_mut() {
+        let chunk_pos = scanner_chunk_pos(g_transform);
         let chunk_pos_changed = chunk_pos != scanner.prev_chunk_pos;
         scanner.prev_chunk_pos = chunk_pos;
         if !chunk_pos_changed {
-            return;
+            continue;
         }
-        let load_data_area = scanner
+        any_moved = true;
+        let data_area = scanner
             .data_sampling_offsets
             .iter()
             .map(|offset| chunk_pos + *offset)
             .collect::<HashSet<IVec3>>();
 
-        let unload_data_area = scanner
-            .data_sampling_offsets
-            .iter()
-            .map(|offset| previous_chunk_pos + *offset)
-            .collect::<HashSet<IVec3>>();
-
-        let load_mesh_area = scanner
+        let mesh_area = scanner
             .mesh_sampling_offsets
             .iter()
             .map(|offset| chunk_pos + *offset)
             .collect::<HashSet<IVec3>>();
 
-        let unload_mesh_area = scanner
-            .mesh_sampling_offsets
-            .iter()
-            .map(|offset| previous_chunk_pos + *offset)
-            .collect::<HashSet<IVec3>>();
+        let previous_area = scanned_areas.0.remove(&entity).unwrap_or_default();
 
-        let data_load = load_data_area.difference(&unload_data_area);
-        let data_unload = unload_data_area.difference(&load_data_area);
-        let mesh_load = load_mesh_area.difference(&unload_mesh_area);
-        let mesh_unload = unload_mesh_area.difference(&load_mesh_area);
+        let Engine {
+            data_refs,
+            mesh_refs,
+            ..
+        } = voxel_engine.as_mut();
+        let data_load = acquire(
+            data_refs,
+            data_area.difference(&previous_area.data).copied(),
+        );
+        let data_unload = release(
+            data_refs,
+            previous_area.data.difference(&data_area).copied(),
+        );
+        let mesh_load = acquire(
+            mesh_refs,
+            mesh_area.difference(&previous_area.mesh).copied(),
+        );
+        let mesh_unload = release(
+            mesh_refs,
+            previous_area.mesh.difference(&mesh_area).copied(),
+        );
+
+        scanned_areas.0.insert(
+            entity,
+            ScannedArea {
+                data: data_area,
+                mesh: mesh_area,
+            },
+        );
 
         scanner.unresolved_data_load.extend(data_load);
         scanner.unresolved_data_unload.extend(data_unload);
@@ -222,6 +346,15 @@ fn detect_move(
                 .cmp(&b.distance_squared(chunk_pos))
         });
     }
+
+    // chunks now closer or further away need a different level of detail
+    if any_moved {
+        let scan_positions = scanners
+            .iter()
+            .map(|(_, scanner, _)| scanner.prev_chunk_pos)
+            .collect::<Vec<_>>();
+        voxel_engine.requeue_lod_changes(&scan_positions);
+    }
 }
 
 ///! constructs spherical positions with the provided chunk radius
@@ -246,12 +379,17 @@ pub fn scan_data(
     mut voxel_engine: ResMut<Engine>,
 ) {
     for (mut scanner, _g_transform) in scanners.iter_mut() {
+        // the other scanners get their turn too
         if voxel_engine.data_tasks.len() >= MAX_DATA_TASKS {
-            return;
+            continue;
         }
         let l = scanner.unresolved_data_load.len();
         // for chunk_pos in scanner.unresolved_data_load.drain(..) {
         for chunk_pos in scanner.unresolved_data_load.drain(0..MAX_SCANS.min(l)) {
+            // every scanner moved away from it in the meantime
+            if !voxel_engine.data_refs.contains_key(&chunk_pos) {
+                continue;
+            }
             // want to load chunk
             let is_busy = voxel_engine.world_data.contains_key(&chunk_pos)
                 || voxel_engine.load_data_queue.contains(&chunk_pos)
@@ -281,8 +419,13 @@ pub fn scan_data_unload(
     // find all loaded and check if in range
     for (mut scanner, _g_transform) in scanners.iter_mut() {
         for chunk_pos in scanner.unresolved_data_unload.drain(..) {
-            // want to load chunk
-            let is_busy = !voxel_engine.world_data.contains_key(&chunk_pos);
+            // still generating, nobody needs it anymore
+            if !voxel_engine.data_refs.contains_key(&chunk_pos) {
+                voxel_engine.cancel_data_task(chunk_pos);
+            }
+            // want to unload chunk, unless another scanner still covers it
+            let is_busy = !voxel_engine.world_data.contains_key(&chunk_pos)
+                || voxel_engine.data_refs.contains_key(&chunk_pos);
             if !is_busy {
                 voxel_engine.unload_data_queue.push(chunk_pos);
             }
@@ -294,6 +437,11 @@ pub fn scan_mesh_unload(mut scanners: Query<&mut Scanner>, mut voxel_engine: Res
     // find all loaded and check if in range
     for mut scanner in scanners.iter_mut() {
         for chunk_pos in scanner.unresolved_mesh_unload.drain(..) {
+            // another scanner still covers it
+            if voxel_engine.mesh_refs.contains_key(&chunk_pos) {
+                continue;
+            }
+            voxel_engine.cancel_mesh_task(chunk_pos);
             voxel_engine.unload_mesh_queue.push(chunk_pos);
         }
     }
@@ -307,12 +455,16 @@ pub fn scan_mesh(mut scanners: Query<&mut Scanner>, mut voxel_engine: ResMut<Eng
         let mut retries = Vec::new();
         let l = scanner.unresolved_mesh_load.len();
         for chunk_pos in scanner.unresolved_mesh_load.drain(0..MAX_SCANS.min(l)) {
+            // every scanner moved away from it in the meantime
+            if !voxel_engine.mesh_refs.contains_key(&chunk_pos) {
+                continue;
+            }
             let mut busy = voxel_engine.load_mesh_queue.contains(&chunk_pos);
-            // all data available
+            // all data available and lit, chunks are only lit once loaded
             busy |= !ADJACENT_CHUNK_DIRECTIONS
                 .iter()
                 .map(|of| chunk_pos + *of)
-                .all(|p| voxel_engine.world_data.contains_key(&p));
+                .all(|p| voxel_engine.world_light.contains_key(&p));
 
             if !busy {
                 voxel_engine.load_mesh_queue.push(chunk_pos);
@@ -0,0 +1,227 @@
+use std::{f32::consts::TAU, fs, io, path::Path};
+
+use bevy::{pbr::light_consts::lux::FULL_DAYLIGHT, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    engine::{Engine, select_world_storage},
+    utils::cli_arg,
+};
+
+///! real seconds a day lasts, unless `--day-length <seconds>` is given
+pub const DEFAULT_DAY_LENGTH: f32 = 20.0 * 60.0;
+
+///! hour a new world starts at
+pub const DEFAULT_HOURS: f32 = 8.0;
+
+///! the time of day is saved next to the regions of the world
+pub const TIME_OF_DAY_FILE: &str = "time.ron";
+
+///! move the time an hour back or forward
+pub const TIME_BACK_KEY: KeyCode = KeyCode::BracketLeft;
+pub const TIME_FORWARD_KEY: KeyCode = KeyCode::BracketRight;
+///! stop or restart the time
+pub const TIME_FREEZE_KEY: KeyCode = KeyCode::KeyP;
+
+const DAY_SKY: Color = Color::srgb(0.5, 0.7, 1.0);
+const DUSK_SKY: Color = Color::srgb(0.9, 0.5, 0.3);
+const NIGHT_SKY: Color = Color::srgb(0.01, 0.01, 0.04);
+const DAY_AMBIENT: Color = Color::srgb(0.9, 0.95, 1.0);
+const NIGHT_AMBIENT: Color = Color::srgb(0.3, 0.35, 0.6);
+const DAY_AMBIENT_BRIGHTNESS: f32 = 80.0;
+const NIGHT_AMBIENT_BRIGHTNESS: f32 = 5.0;
+
+///! the clock of the world, drives the sun, the ambient light and the sky color
+#[derive(Resource, Clone, Debug)]
+pub struct TimeOfDay {
+    ///! hour of the day from 0 to 24, the sun rises at 6 and sets at 18
+    pub hours: f32,
+    ///! real seconds a full day takes
+    pub day_length: f32,
+    ///! time stands still
+    pub frozen: bool,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        let day_length = cli_arg("--day-length")
+            .and_then(|arg| arg.parse::<f32>().ok())
+            .filter(|secs| *secs > 0.0)
+            .unwrap_or(DEFAULT_DAY_LENGTH);
+        Self {
+            hours: DEFAULT_HOURS,
+            day_length,
+            frozen: false,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn set_hours(&mut self, hours: f32) {
+        self.hours = hours.rem_euclid(24.0);
+    }
+
+    ///! move the clock by real seconds, unless frozen
+    pub fn advance(&mut self, secs: f32) {
+        if self.frozen {
+            return;
+        }
+        self.set_hours(self.hours + secs / self.day_length * 24.0);
+    }
+
+    ///! unit vector pointing at the sun, it rises in +x and culminates at noon
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.hours - 6.0) / 24.0 * TAU;
+        Vec3::new(angle.cos(), angle.sin(), 0.3).normalize()
+    }
+
+    ///! 0 at night to 1 in daytime, fading while the sun is close to the horizon
+    pub fn daylight(&self) -> f32 {
+        (self.sun_direction().y * 4.0).clamp(0.0, 1.0)
+    }
+}
+
+///! what is written to TIME_OF_DAY_FILE, the day length is a setting of the player
+#[derive(Serialize, Deserialize)]
+struct SavedTimeOfDay {
+    hours: f32,
+    frozen: bool,
+}
+
+///! marks the directional light moved by the time of day
+#[derive(Component)]
+pub struct Sun;
+
+pub struct TimeOfDayPlugin;
+
+impl Plugin for TimeOfDayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>();
+        app.add_systems(
+            Startup,
+            (load_time_of_day.after(select_world_storage), spawn_sun),
+        );
+        app.add_systems(
+            Update,
+            (time_of_day_keys, advance_time_of_day, apply_time_of_day).chain(),
+        );
+        app.add_systems(Last, save_time_of_day_on_exit);
+    }
+}
+
+///! restore the saved time of the world, `--time <hours>` overrides it
+fn load_time_of_day(voxel_engine: Res<Engine>, mut time_of_day: ResMut<TimeOfDay>) {
+    match read_time_of_day(voxel_engine.storage.dir()) {
+        Ok(Some(saved)) => {
+            time_of_day.set_hours(saved.hours);
+            time_of_day.frozen = saved.frozen;
+        }
+        Ok(None) => {}
+        Err(e) => error!("failed to load the time of day: {e}"),
+    }
+    if let Some(hours) = cli_arg("--time").and_then(|arg| arg.parse::<f32>().ok()) {
+        time_of_day.set_hours(hours);
+    }
+}
+
+fn read_time_of_day(dir: &Path) -> io::Result<Option<SavedTimeOfDay>> {
+    let text = match fs::read_to_string(dir.join(TIME_OF_DAY_FILE)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    ron::from_str(&text)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_time_of_day(dir: &Path, time_of_day: &TimeOfDay) -> io::Result<()> {
+    let saved = SavedTimeOfDay {
+        hours: time_of_day.hours,
+        frozen: time_of_day.frozen,
+    };
+    let text = ron::ser::to_string_pretty(&saved, ron::ser::PrettyConfig::default())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(TIME_OF_DAY_FILE), text)
+}
+
+///! save the time of day when the app is closing, alongside the chunks
+fn save_time_of_day_on_exit(
+    mut exit_events: EventReader<AppExit>,
+    voxel_engine: Res<Engine>,
+    time_of_day: Res<TimeOfDay>,
+) {
+    if exit_events.read().last().is_none() {
+        return;
+    }
+    if let Err(e) = write_time_of_day(voxel_engine.storage.dir(), &time_of_day) {
+        error!("failed to save the time of day: {e}");
+    }
+}
+
+fn spawn_sun(mut commands: Commands) {
+    commands.spawn((
+        Sun,
+        DirectionalLight {
+            illuminance: FULL_DAYLIGHT,
+            ..default()
+        },
+        Transform::default(),
+    ));
+}
+
+fn time_of_day_keys(keyboard_input: Res<ButtonInput<KeyCode>>, mut time_of_day: ResMut<TimeOfDay>) {
+    let hours = time_of_day.hours;
+    if keyboard_input.just_pressed(TIME_BACK_KEY) {
+        time_of_day.set_hours(hours - 1.0);
+    }
+    if keyboard_input.just_pressed(TIME_FORWARD_KEY) {
+        time_of_day.set_hours(hours + 1.0);
+    }
+    if keyboard_input.just_pressed(TIME_FREEZE_KEY) {
+        time_of_day.frozen = !time_of_day.frozen;
+        info!(
+            "time of day {}",
+            if time_of_day.frozen {
+                "frozen"
+            } else {
+                "running"
+            }
+        );
+    }
+}
+
+fn advance_time_of_day(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    if time_of_day.frozen {
+        return;
+    }
+    time_of_day.advance(time.delta_secs());
+}
+
+///! point the sun and tint the ambient light and the sky for the current time
+fn apply_time_of_day(
+    time_of_day: Res<TimeOfDay>,
+    mut suns: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+    mut ambient_light: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    if !time_of_day.is_changed() {
+        return;
+    }
+    let sun_direction = time_of_day.sun_direction();
+    let daylight = time_of_day.daylight();
+
+    for (mut transform, mut light) in suns.iter_mut() {
+        // directional lights shine along their forward axis
+        *transform = Transform::default().looking_to(-sun_direction, Vec3::Z);
+        light.illuminance = FULL_DAYLIGHT * daylight;
+    }
+
+    ambient_light.color = NIGHT_AMBIENT.mix(&DAY_AMBIENT, daylight);
+    ambient_light.brightness = NIGHT_AMBIENT_BRIGHTNESS.lerp(DAY_AMBIENT_BRIGHTNESS, daylight);
+
+    // reddish while the sun is around the horizon
+    let dusk = (1.0 - sun_direction.y.abs() * 4.0).clamp(0.0, 1.0);
+    clear_color.0 = NIGHT_SKY.mix(&DAY_SKY, daylight).mix(&DUSK_SKY, dusk * 0.6);
+}
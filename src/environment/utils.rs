@@ -1,4 +1,11 @@
-use bevy::math::IVec3;
+use std::path::PathBuf;
+
+use bevy::{
+    asset::io::file::FileAssetReader,
+    math::{IVec3, UVec2},
+};
+
+use super::chunk::CHUNK_SIZE_I32;
 
 ///! generate a vec of indices
 ///! assumes vertices are made of quads, and counter clockwise ordered
@@ -83,3 +90,34 @@ pub fn make_vertex_u32(
     // | (normal as u32) << 18u32
     // | (texture_id) << 21u32
 }
+
+///! second word of a chunk vertex: texture coordinates in blocks, so a texture repeats
+///! once per block across greedy quads, the texture array layer and the packed voxel light
+// uv 6+6 bits, layer 8 bits, sky light 4 bits, block light 4 bits
+#[inline]
+pub fn make_vertex_uv_u32(uv: UVec2, layer: u32, light: u32) -> u32 {
+    uv.x | uv.y << 6u32 | layer << 12u32 | (light >> 4) << 20u32 | (light & 0xf) << 24u32
+}
+
+///! split a world voxel position into its chunk position and the position local to that chunk
+#[inline]
+pub fn world_to_chunk(world_pos: IVec3) -> (IVec3, IVec3) {
+    let size = IVec3::splat(CHUNK_SIZE_I32);
+    (world_pos.div_euclid(size), world_pos.rem_euclid(size))
+}
+
+///! folder the asset server reads from, the apps give it to AssetPlugin::file_path
+pub const ASSETS_DIR: &str = "assets";
+
+///! the asset folder on disk, resolved like the asset server does,
+///! for code reading or writing asset files without it
+pub fn assets_path() -> PathBuf {
+    FileAssetReader::get_base_path().join(ASSETS_DIR)
+}
+
+///! value following a `--flag value` pair on the command line
+pub fn cli_arg(flag: &str) -> Option<String> {
+    let mut args = std::env::args();
+    args.by_ref().find(|arg| arg == flag)?;
+    args.next()
+}
@@ -0,0 +1,67 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use super::{
+    block::BlockType,
+    block_registry::BlockRegistry,
+    engine::{ChunkModification, Engine},
+    raycast::VoxelRayHit,
+    utils::world_to_chunk,
+};
+
+///! sent once a block edit has been applied to the chunk data
+#[derive(Event, Copy, Clone, Debug)]
+pub struct BlockChanged {
+    ///! world voxel position
+    pub pos: IVec3,
+    pub old: BlockType,
+    pub new: BlockType,
+}
+
+///! read and edit blocks by world position, without knowing about chunks
+///!
+///! edits are queued and applied by start_modifications,
+///! which sends a BlockChanged event for each of them
+#[derive(SystemParam)]
+pub struct VoxelWorld<'w> {
+    engine: ResMut<'w, Engine>,
+    registry: Res<'w, BlockRegistry>,
+}
+
+impl VoxelWorld<'_> {
+    ///! None if the block's chunk is not loaded
+    pub fn get_block(&self, world_pos: IVec3) -> Option<BlockType> {
+        self.engine
+            .get_block(world_pos)
+            .map(|block| block.block_type)
+    }
+
+    pub fn set_block(&mut self, world_pos: IVec3, block_type: BlockType) {
+        let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+        self.engine
+            .chunk_modifications
+            .entry(chunk_pos)
+            .or_default()
+            .push(ChunkModification(local_pos, block_type));
+    }
+
+    pub fn set_blocks(&mut self, blocks: impl IntoIterator<Item = (IVec3, BlockType)>) {
+        for (world_pos, block_type) in blocks {
+            self.set_block(world_pos, block_type);
+        }
+    }
+
+    ///! first solid block along a ray, see Engine::raycast
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<VoxelRayHit> {
+        self.engine
+            .raycast(&self.registry, origin, direction, max_distance)
+    }
+
+    pub fn is_solid(&self, world_pos: IVec3) -> bool {
+        self.get_block(world_pos)
+            .is_some_and(|block_type| self.registry.is_solid(block_type))
+    }
+
+    pub fn registry(&self) -> &BlockRegistry {
+        &self.registry
+    }
+}
@@ -0,0 +1,3 @@
+pub mod environment;
+pub mod network;
+pub mod player;
@@ -1,29 +1,41 @@
-use std::f32::consts::PI;
-
 use bevy::app::TaskPoolThreadAssignmentPolicy;
+use bevy::prelude::*;
 use bevy::render::RenderPlugin;
 use bevy::render::settings::{RenderCreation, WgpuFeatures, WgpuSettings};
 use bevy::window::{CursorGrabMode, PrimaryWindow};
-use bevy::{pbr::light_consts::lux::FULL_DAYLIGHT, prelude::*};
-use environment::plugin::EnvironmentPlugin;
-use environment::rendering::{
-    ChunkMaterial, ChunkMaterialWireframe, GlobalChunkMaterial, GlobalChunkWireframeMaterial,
-    RenderingPlugin,
+use guncruft::environment::export::{EXPORT_COMMAND, run_export_command};
+use guncruft::environment::plugin::{ChunkMeshPlugin, EnvironmentPlugin};
+use guncruft::environment::rendering::{
+    ChunkMaterial, ChunkMaterialWireframe, GlobalChunkMaterial, GlobalChunkTransparentMaterial,
+    GlobalChunkWireframeMaterial, RenderingPlugin, TRANSPARENT_MESH_ORIGIN,
 };
-use environment::scanner::ScannerPlugin;
-use player::{
+use guncruft::environment::scanner::ScannerPlugin;
+use guncruft::environment::time_of_day::TimeOfDayPlugin;
+use guncruft::environment::utils::ASSETS_DIR;
+use guncruft::network::client::ClientPlugin;
+use guncruft::player::{
+    creative_mode::{lay_cube, select_block, toggle_building, toggle_movement_mode},
+    debug_overlay::DebugOverlayPlugin,
     fps_camera::move_camera,
     fps_movement::{advance_fps_movement, handle_fps_movement, interpolate_fps_movement},
+    gun::GunPlugin,
     player::create_player,
+    remote_player::sync_remote_players,
 };
 
-pub mod environment;
-pub mod player;
-
 #[derive(Component)]
 struct Controlable;
 
 fn main() {
+    // `guncruft export ...` writes a region of the world to a file without opening a window
+    if std::env::args().nth(1).as_deref() == Some(EXPORT_COMMAND) {
+        if let Err(e) = run_export_command() {
+            eprintln!("export failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .add_plugins((DefaultPlugins
             .set(RenderPlugin {
@@ -34,6 +46,10 @@ fn main() {
                 }),
                 ..default()
             })
+            .set(AssetPlugin {
+                file_path: ASSETS_DIR.to_string(),
+                ..default()
+            })
             .set(TaskPoolPlugin {
                 task_pool_options: TaskPoolOptions {
                     async_compute: TaskPoolThreadAssignmentPolicy {
@@ -47,11 +63,25 @@ fn main() {
                 },
             }),))
         .add_plugins(EnvironmentPlugin)
-        .add_plugins(ScannerPlugin)
+        .add_plugins(ChunkMeshPlugin)
         .add_plugins(ScannerPlugin)
         .add_plugins(RenderingPlugin)
+        .add_plugins(TimeOfDayPlugin)
+        .add_plugins(DebugOverlayPlugin)
+        .add_plugins(ClientPlugin::default())
+        .add_plugins(GunPlugin)
         .add_systems(Startup, (setup_world, create_player))
-        .add_systems(Update, (move_camera, animate_light))
+        .add_systems(
+            Update,
+            (
+                move_camera,
+                select_block,
+                lay_cube,
+                toggle_movement_mode,
+                toggle_building,
+                sync_remote_players,
+            ),
+        )
         .add_systems(FixedUpdate, advance_fps_movement)
         .add_systems(
             // The `RunFixedMainLoop` schedule allows us to schedule systems to run before and after the fixed timestep loop.
@@ -72,10 +102,8 @@ fn main() {
 
 fn setup_world(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
     mut chunk_materials: ResMut<Assets<ChunkMaterial>>,
     mut chunk_materials_wireframe: ResMut<Assets<ChunkMaterialWireframe>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
 
     mut q_windows: Query<&mut Window, With<PrimaryWindow>>,
 ) {
@@ -89,63 +117,33 @@ fn setup_world(
     primary_window.cursor_options.visible = false;
 
     // Block materials
-    commands.insert_resource(
-        (GlobalChunkMaterial(chunk_materials.add(ChunkMaterial {
+    commands.insert_resource(GlobalChunkMaterial(MeshMaterial3d(chunk_materials.add(
+        ChunkMaterial {
             reflectance: 0.5,
             perceptual_roughness: 1.0,
             metallic: 0.01,
-        }))),
-    );
-    commands.insert_resource(GlobalChunkWireframeMaterial(chunk_materials_wireframe.add(
-        MeshMaterial3d(ChunkMaterialWireframe {
+            origin_offset: 0.0,
+            textures: None,
+            alpha_mode: AlphaMode::Opaque,
+        },
+    ))));
+    commands.insert_resource(GlobalChunkTransparentMaterial(MeshMaterial3d(
+        chunk_materials.add(ChunkMaterial {
+            reflectance: 0.5,
+            perceptual_roughness: 0.2,
+            metallic: 0.01,
+            origin_offset: -TRANSPARENT_MESH_ORIGIN,
+            textures: None,
+            alpha_mode: AlphaMode::Blend,
+        }),
+    )));
+    commands.insert_resource(GlobalChunkWireframeMaterial(MeshMaterial3d(
+        chunk_materials_wireframe.add(ChunkMaterialWireframe {
             reflectance: 0.5,
             perceptual_roughness: 1.0,
             metallic: 0.01,
+            origin_offset: 0.0,
+            textures: None,
         }),
     )));
-
-    // light
-    commands.spawn((
-        DirectionalLight {
-            illuminance: FULL_DAYLIGHT,
-            ..default()
-        },
-        Transform::from_xyz(-5.0, 10.0, -5.0).with_rotation(Quat::from_rotation_x(-PI / 4.)),
-    ));
-
-    // Chessboard Plane
-    let black_material = materials.add(Color::BLACK);
-    let white_material = materials.add(Color::WHITE);
-
-    let plane_mesh = meshes.add(Plane3d::default().mesh().size(2.0, 2.0));
-
-    for x in -3..4 {
-        for z in -3..4 {
-            commands.spawn((
-                Mesh3d(plane_mesh.clone()),
-                MeshMaterial3d(if (x + z) % 2 == 0 {
-                    black_material.clone()
-                } else {
-                    white_material.clone()
-                }),
-                Transform::from_xyz(x as f32 * 2.0, -1.0, z as f32 * 2.0),
-            ));
-        }
-    }
-}
-
-/// Moves the light around.
-fn animate_light(
-    mut lights: Query<&mut Transform, Or<(With<PointLight>, With<DirectionalLight>)>>,
-    time: Res<Time>,
-) {
-    let now = time.elapsed_secs();
-    for mut transform in lights.iter_mut() {
-        transform.translation = vec3(
-            ops::sin(now * 1.4),
-            ops::cos(now * 1.0),
-            ops::cos(now * 0.6),
-        ) * vec3(1.0, 4.0, 1.0);
-        transform.look_at(Vec3::ZERO, Vec3::Y);
-    }
 }
@@ -0,0 +1,177 @@
+use bevy::prelude::*;
+
+use crate::{
+    environment::{
+        chunk::CHUNK_SIZE_I32,
+        engine::{ChunkModification, Engine, start_modifications},
+        utils::{cli_arg, world_to_chunk},
+    },
+    player::fps_movement::advance_fps_movement,
+};
+
+use super::{
+    interpolation::{InterpolationDelay, RemotePlayers},
+    prediction::{Prediction, reconcile_movement, record_prediction, send_input},
+    protocol::{ClientMessage, Connection, PROTOCOL_VERSION, PlayerId, ServerMessage},
+};
+
+///! plays on a server instead of a local world, with `--connect <addr>`
+///! without an address the plugin does nothing
+pub struct ClientPlugin {
+    pub addr: Option<String>,
+}
+
+impl Default for ClientPlugin {
+    fn default() -> Self {
+        Self {
+            addr: cli_arg("--connect"),
+        }
+    }
+}
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(addr) = &self.addr else {
+            return;
+        };
+        let connection = match Connection::connect(addr) {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to connect to {addr}, playing offline: {e}");
+                return;
+            }
+        };
+        info!("connected to {addr}");
+        app.insert_resource(NetClient {
+            connection,
+            player_id: None,
+        });
+        app.init_resource::<RemotePlayers>();
+        app.init_resource::<InterpolationDelay>();
+        app.init_resource::<Prediction>();
+        app.add_systems(Startup, stream_world);
+        app.add_systems(
+            Update,
+            (
+                (send_block_edits, receive_server_messages)
+                    .chain()
+                    .before(start_modifications)
+                    .run_if(resource_exists::<NetClient>),
+                reconcile_movement.after(receive_server_messages),
+            ),
+        );
+        app.add_systems(
+            FixedUpdate,
+            (
+                send_input
+                    .before(advance_fps_movement)
+                    .run_if(resource_exists::<NetClient>),
+                record_prediction.after(advance_fps_movement),
+            ),
+        );
+        app.add_systems(Last, flush_client.run_if(resource_exists::<NetClient>));
+    }
+}
+
+#[derive(Resource)]
+pub struct NetClient {
+    connection: Connection,
+    ///! given by the server once it welcomed us
+    pub player_id: Option<PlayerId>,
+}
+
+impl NetClient {
+    pub fn send(&mut self, message: &ClientMessage) {
+        self.connection.send(message);
+    }
+}
+
+fn stream_world(mut voxel_engine: ResMut<Engine>) {
+    voxel_engine.streamed = true;
+}
+
+///! edits are requested from the server instead of applied, it sends back what it applied
+fn send_block_edits(mut client: ResMut<NetClient>, mut voxel_engine: ResMut<Engine>) {
+    for (chunk_pos, mods) in voxel_engine.chunk_modifications.drain() {
+        for ChunkModification(local_pos, block_type) in mods {
+            client.connection.send(&ClientMessage::SetBlock {
+                pos: chunk_pos * CHUNK_SIZE_I32 + local_pos,
+                block_type,
+            });
+        }
+    }
+}
+
+fn receive_server_messages(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut client: ResMut<NetClient>,
+    mut voxel_engine: ResMut<Engine>,
+    mut remote_players: ResMut<RemotePlayers>,
+    mut prediction: ResMut<Prediction>,
+) {
+    let messages = match client.connection.receive::<ServerMessage>() {
+        Ok(messages) => messages,
+        Err(e) => {
+            error!("lost the connection to the server: {e}");
+            commands.remove_resource::<NetClient>();
+            return;
+        }
+    };
+    for message in messages {
+        match message {
+            ServerMessage::Welcome { version, player_id } => {
+                if version != PROTOCOL_VERSION {
+                    error!("the server speaks protocol {version}, we speak {PROTOCOL_VERSION}");
+                    commands.remove_resource::<NetClient>();
+                    return;
+                }
+                info!("joined as player {}", player_id.0);
+                client.player_id = Some(player_id);
+            }
+            ServerMessage::Chunk { chunk_pos, data } => {
+                voxel_engine.insert_chunk(chunk_pos, data);
+            }
+            ServerMessage::ForgetChunk { chunk_pos } => {
+                // edits to it aren't sent anymore, our scanner may still want it but can't keep it
+                voxel_engine.forget_chunk(chunk_pos);
+            }
+            ServerMessage::BlockEdits(edits) => {
+                for (world_pos, block_type) in edits {
+                    let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+                    voxel_engine
+                        .chunk_modifications
+                        .entry(chunk_pos)
+                        .or_default()
+                        .push(ChunkModification(local_pos, block_type));
+                }
+            }
+            ServerMessage::PlayerState {
+                player_id,
+                time: server_time,
+                state,
+            } => {
+                remote_players.push(player_id, server_time, state, time.elapsed_secs_f64());
+            }
+            ServerMessage::MovementAck { tick, state } => {
+                // only the latest matters, older inputs are acknowledged with it
+                prediction.ack = Some((tick, state));
+            }
+            ServerMessage::PlayerLeft { player_id } => {
+                remote_players.remove(player_id);
+            }
+        }
+    }
+}
+
+fn flush_client(mut commands: Commands, mut client: ResMut<NetClient>) {
+    if let Err(e) = client.connection.flush() {
+        error!("lost the connection to the server: {e}");
+        commands.remove_resource::<NetClient>();
+        return;
+    }
+    if client.connection.is_closed() {
+        error!("the server closed the connection");
+        commands.remove_resource::<NetClient>();
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::VecDeque;
+
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::environment::utils::cli_arg;
+
+use super::protocol::{PlayerId, PlayerState};
+
+///! how far behind the server other players are shown, unless `--interpolation-delay <ms>` is given
+pub const DEFAULT_INTERPOLATION_DELAY: f32 = 0.1;
+///! snapshots older than this, in seconds, are dropped
+const MAX_SNAPSHOT_AGE: f64 = 1.0;
+///! how quickly the estimated server clock follows new snapshots
+const CLOCK_SMOOTHING: f64 = 0.05;
+///! a snapshot this far off the estimated server clock resets it, in seconds
+const CLOCK_RESET: f64 = 0.5;
+
+///! seconds other players are shown in the past, so there are two snapshots to blend between
+#[derive(Resource, Copy, Clone, Debug)]
+pub struct InterpolationDelay(pub f32);
+
+impl Default for InterpolationDelay {
+    fn default() -> Self {
+        let delay = cli_arg("--interpolation-delay")
+            .and_then(|arg| arg.parse::<f32>().ok())
+            .map(|ms| ms / 1000.0)
+            .unwrap_or(DEFAULT_INTERPOLATION_DELAY);
+        InterpolationDelay(delay.max(0.0))
+    }
+}
+
+///! recent states the server sent of every other player, by server time
+#[derive(Resource, Default)]
+pub struct RemotePlayers {
+    snapshots: HashMap<PlayerId, VecDeque<(f64, PlayerState)>>,
+    ///! server time minus local time, smoothed over the snapshots
+    clock_offset: Option<f64>,
+}
+
+impl RemotePlayers {
+    ///! a state of player_id at server time, received at local time
+    pub fn push(&mut self, player_id: PlayerId, time: f64, state: PlayerState, local_time: f64) {
+        let offset = time - local_time;
+        self.clock_offset = match self.clock_offset {
+            Some(current) if (offset - current).abs() < CLOCK_RESET => {
+                Some(current + (offset - current) * CLOCK_SMOOTHING)
+            }
+            _ => Some(offset),
+        };
+
+        let snapshots = self.snapshots.entry(player_id).or_default();
+        snapshots.push_back((time, state));
+        while snapshots
+            .front()
+            .is_some_and(|(t, _)| *t < time - MAX_SNAPSHOT_AGE)
+        {
+            snapshots.pop_front();
+        }
+    }
+
+    pub fn remove(&mut self, player_id: PlayerId) {
+        self.snapshots.remove(&player_id);
+    }
+
+    pub fn player_ids(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.snapshots.keys().copied()
+    }
+
+    ///! the server time to show other players at, None before the first snapshot
+    pub fn render_time(&self, local_time: f64, delay: InterpolationDelay) -> Option<f64> {
+        Some(local_time + self.clock_offset? - delay.0 as f64)
+    }
+
+    ///! the state of player_id at server time, blended between the snapshots around it
+    ///! held at the first or the last snapshot outside of them
+    pub fn sample(&self, player_id: PlayerId, time: f64) -> Option<PlayerState> {
+        let snapshots = self.snapshots.get(&player_id)?;
+        let (first_time, first) = snapshots.front()?;
+        if time <= *first_time {
+            return Some(*first);
+        }
+        for ((t0, s0), (t1, s1)) in snapshots.iter().zip(snapshots.iter().skip(1)) {
+            if time < *t1 {
+                let alpha = ((time - t0) / (t1 - t0).max(f64::EPSILON)) as f32;
+                return Some(PlayerState {
+                    translation: s0.translation.lerp(s1.translation, alpha),
+                    velocity: s0.velocity.lerp(s1.velocity, alpha),
+                    rotation: s0.rotation.slerp(s1.rotation, alpha),
+                    grounded: s1.grounded,
+                });
+            }
+        }
+        snapshots.back().map(|(_, state)| *state)
+    }
+}
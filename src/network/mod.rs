@@ -0,0 +1,5 @@
+pub mod client;
+pub mod interpolation;
+pub mod prediction;
+pub mod protocol;
+pub mod server;
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{
+    environment::{block_registry::BlockRegistry, engine::Engine},
+    player::{
+        fps_camera::FPSCamera,
+        fps_movement::{FPSMovement, MovementInput, MovementMode},
+    },
+};
+
+use super::{
+    client::NetClient,
+    protocol::{ClientMessage, PlayerState},
+};
+
+///! fixed timesteps kept for replaying, two seconds at the default rate
+const MAX_HISTORY: usize = 128;
+///! predictions closer than this to where the server put the player are kept
+const CORRECTION_DISTANCE: f32 = 0.01;
+
+///! the local player moves right away, its inputs are kept until the server acknowledged them
+///! so they can be replayed on top of a server correction
+#[derive(Resource, Default)]
+pub struct Prediction {
+    ///! number of the last fixed timestep sent
+    pub tick: u32,
+    ///! sent inputs the server didn't acknowledge yet, oldest first
+    history: VecDeque<PredictedStep>,
+    ///! the latest state the server acknowledged, waiting for reconcile_movement
+    pub ack: Option<(u32, PlayerState)>,
+    ///! times the prediction was wrong
+    pub corrections: u64,
+}
+
+struct PredictedStep {
+    tick: u32,
+    input: MovementInput,
+    mode: MovementMode,
+    ///! where the step left the player
+    translation: Vec3,
+}
+
+///! number the coming fixed timestep, send its input and remember it
+///! runs right before advance_fps_movement consumes the input
+pub fn send_input(
+    mut client: ResMut<NetClient>,
+    mut prediction: ResMut<Prediction>,
+    players: Query<(&FPSMovement, &MovementMode, &Transform), With<FPSCamera>>,
+) {
+    let Ok((mov, mode, transform)) = players.single() else {
+        return;
+    };
+    prediction.tick = prediction.tick.wrapping_add(1);
+    let input = mov.input();
+    client.send(&ClientMessage::Input {
+        tick: prediction.tick,
+        input,
+        mode: *mode,
+        rotation: transform.rotation,
+    });
+    if prediction.history.len() >= MAX_HISTORY {
+        prediction.history.pop_front();
+    }
+    let tick = prediction.tick;
+    prediction.history.push_back(PredictedStep {
+        tick,
+        input,
+        mode: *mode,
+        translation: mov.phys_translation,
+    });
+}
+
+///! store where the fixed timestep predicted the player, runs after advance_fps_movement
+pub fn record_prediction(
+    mut prediction: ResMut<Prediction>,
+    players: Query<&FPSMovement, With<FPSCamera>>,
+) {
+    let Ok(mov) = players.single() else {
+        return;
+    };
+    if let Some(step) = prediction.history.back_mut() {
+        step.translation = mov.phys_translation;
+    }
+}
+
+///! compare the acknowledged state with what was predicted for its tick,
+///! on a mismatch rewind to the server's state and replay the inputs sent since
+pub fn reconcile_movement(
+    fixed_time: Res<Time<Fixed>>,
+    voxel_engine: Res<Engine>,
+    registry: Res<BlockRegistry>,
+    mut prediction: ResMut<Prediction>,
+    mut players: Query<(&mut FPSMovement, &MovementMode), With<FPSCamera>>,
+) {
+    let Some((tick, state)) = prediction.ack.take() else {
+        return;
+    };
+    let Ok((mut mov, mode)) = players.single_mut() else {
+        return;
+    };
+
+    // ticks wrap around, compare them by difference
+    let acked = |step: &PredictedStep| (tick.wrapping_sub(step.tick) as i32) >= 0;
+    let mut predicted = None;
+    while prediction.history.front().is_some_and(acked) {
+        predicted = prediction.history.pop_front();
+    }
+    let correct = predicted.is_some_and(|step| {
+        step.tick == tick && step.translation.distance(state.translation) <= CORRECTION_DISTANCE
+    });
+    if correct {
+        return;
+    }
+
+    prediction.corrections += 1;
+    let pending_input = mov.input();
+    mov.phys_translation = state.translation;
+    mov.velocity = state.velocity;
+    mov.grounded = state.grounded;
+    let dt = fixed_time.timestep().as_secs_f32();
+    for step in prediction.history.iter_mut() {
+        mov.step(step.input, step.mode, dt, &voxel_engine, &registry);
+        step.translation = mov.phys_translation;
+    }
+    // what handle_fps_movement gathered for the coming timestep still applies
+    mov.velocity.x = pending_input.velocity.x;
+    mov.velocity.z = pending_input.velocity.z;
+    if let MovementMode::Fly { .. } = mode {
+        mov.velocity.y = pending_input.velocity.y;
+    }
+    mov.jump = pending_input.jump;
+}
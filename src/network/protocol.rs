@@ -0,0 +1,833 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::Arc,
+};
+
+use bevy::math::{IVec3, Quat, Vec3};
+
+use crate::{
+    environment::{
+        block::{BlockData, BlockType},
+        chunk::{CHUNK_SIZE3, ChunkData},
+        palette::PackedIndices,
+    },
+    player::fps_movement::{MovementInput, MovementMode},
+};
+
+///! bumped whenever a message changes, client and server must agree on it
+pub const PROTOCOL_VERSION: u32 = 2;
+
+///! port the server listens on, unless `--listen <addr>` is given
+pub const DEFAULT_PORT: u16 = 7777;
+
+///! frames announcing more than this are a broken or hostile peer
+pub const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+///! a connected player, picked by the server
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PlayerId(pub u32);
+
+///! what other players need to know about a player's FPSMovement
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PlayerState {
+    ///! eye position, the FPSMovement physical translation
+    pub translation: Vec3,
+    pub velocity: Vec3,
+    ///! where the camera looks
+    pub rotation: Quat,
+    pub grounded: bool,
+}
+
+pub enum ClientMessage {
+    ///! what the player did during a fixed timestep, the server replays it to move the player
+    Input {
+        ///! numbers the client's fixed timesteps, acknowledged by MovementAck
+        tick: u32,
+        input: MovementInput,
+        mode: MovementMode,
+        rotation: Quat,
+    },
+    ///! asks the server for a block edit, the world only changes once the server sends it back
+    SetBlock { pos: IVec3, block_type: BlockType },
+}
+
+pub enum ServerMessage {
+    ///! first message of a connection
+    Welcome {
+        version: u32,
+        player_id: PlayerId,
+    },
+    ///! a chunk around the player, replaces any copy the client has
+    Chunk {
+        chunk_pos: IVec3,
+        data: Arc<ChunkData>,
+    },
+    ///! the chunk left the player's area, edits to it won't be sent anymore
+    ForgetChunk {
+        chunk_pos: IVec3,
+    },
+    ///! blocks the world ended up with, by world position
+    BlockEdits(Vec<(IVec3, BlockType)>),
+    ///! another player, as the server saw it at time
+    PlayerState {
+        player_id: PlayerId,
+        ///! seconds since the server started
+        time: f64,
+        state: PlayerState,
+    },
+    ///! where the receiving player ended up once the server replayed its inputs up to tick
+    MovementAck {
+        tick: u32,
+        state: PlayerState,
+    },
+    PlayerLeft {
+        player_id: PlayerId,
+    },
+}
+
+///! a message body, framed by Connection
+pub trait Message: Sized {
+    fn encode(&self, w: &mut MessageWriter);
+    fn decode(r: &mut MessageReader) -> io::Result<Self>;
+}
+
+const CLIENT_INPUT: u8 = 0;
+const CLIENT_SET_BLOCK: u8 = 1;
+
+impl Message for ClientMessage {
+    fn encode(&self, w: &mut MessageWriter) {
+        match self {
+            ClientMessage::Input {
+                tick,
+                input,
+                mode,
+                rotation,
+            } => {
+                w.u8(CLIENT_INPUT);
+                w.u32(*tick);
+                w.vec3(input.velocity);
+                w.u8(input.jump as u8);
+                w.movement_mode(*mode);
+                w.quat(*rotation);
+            }
+            ClientMessage::SetBlock { pos, block_type } => {
+                w.u8(CLIENT_SET_BLOCK);
+                w.ivec3(*pos);
+                w.u16(block_type.id());
+            }
+        }
+    }
+
+    fn decode(r: &mut MessageReader) -> io::Result<Self> {
+        match r.u8()? {
+            CLIENT_INPUT => Ok(ClientMessage::Input {
+                tick: r.u32()?,
+                input: MovementInput {
+                    velocity: r.vec3()?,
+                    jump: r.u8()? != 0,
+                },
+                mode: r.movement_mode()?,
+                rotation: r.quat()?,
+            }),
+            CLIENT_SET_BLOCK => Ok(ClientMessage::SetBlock {
+                pos: r.ivec3()?,
+                block_type: BlockType(r.u16()?),
+            }),
+            tag => Err(invalid(format!("unknown client message {tag}"))),
+        }
+    }
+}
+
+const SERVER_WELCOME: u8 = 0;
+const SERVER_CHUNK: u8 = 1;
+const SERVER_FORGET_CHUNK: u8 = 2;
+const SERVER_BLOCK_EDITS: u8 = 3;
+const SERVER_PLAYER_STATE: u8 = 4;
+const SERVER_PLAYER_LEFT: u8 = 5;
+const SERVER_MOVEMENT_ACK: u8 = 6;
+
+impl Message for ServerMessage {
+    fn encode(&self, w: &mut MessageWriter) {
+        match self {
+            ServerMessage::Welcome { version, player_id } => {
+                w.u8(SERVER_WELCOME);
+                w.u32(*version);
+                w.u32(player_id.0);
+            }
+            ServerMessage::Chunk { chunk_pos, data } => {
+                w.u8(SERVER_CHUNK);
+                w.ivec3(*chunk_pos);
+                w.chunk_data(data);
+            }
+            ServerMessage::ForgetChunk { chunk_pos } => {
+                w.u8(SERVER_FORGET_CHUNK);
+                w.ivec3(*chunk_pos);
+            }
+            ServerMessage::BlockEdits(edits) => {
+                w.u8(SERVER_BLOCK_EDITS);
+                w.u32(edits.len() as u32);
+                for (pos, block_type) in edits {
+                    w.ivec3(*pos);
+                    w.u16(block_type.id());
+                }
+            }
+            ServerMessage::PlayerState {
+                player_id,
+                time,
+                state,
+            } => {
+                w.u8(SERVER_PLAYER_STATE);
+                w.u32(player_id.0);
+                w.f64(*time);
+                w.player_state(state);
+            }
+            ServerMessage::MovementAck { tick, state } => {
+                w.u8(SERVER_MOVEMENT_ACK);
+                w.u32(*tick);
+                w.player_state(state);
+            }
+            ServerMessage::PlayerLeft { player_id } => {
+                w.u8(SERVER_PLAYER_LEFT);
+                w.u32(player_id.0);
+            }
+        }
+    }
+
+    fn decode(r: &mut MessageReader) -> io::Result<Self> {
+        match r.u8()? {
+            SERVER_WELCOME => Ok(ServerMessage::Welcome {
+                version: r.u32()?,
+                player_id: PlayerId(r.u32()?),
+            }),
+            SERVER_CHUNK => Ok(ServerMessage::Chunk {
+                chunk_pos: r.ivec3()?,
+                data: Arc::new(r.chunk_data()?),
+            }),
+            SERVER_FORGET_CHUNK => Ok(ServerMessage::ForgetChunk {
+                chunk_pos: r.ivec3()?,
+            }),
+            SERVER_BLOCK_EDITS => {
+                let len = r.u32()? as usize;
+                // each edit takes 14 bytes, don't trust len for the allocation
+                let mut edits = Vec::with_capacity(len.min(r.remaining() / 14));
+                for _ in 0..len {
+                    edits.push((r.ivec3()?, BlockType(r.u16()?)));
+                }
+                Ok(ServerMessage::BlockEdits(edits))
+            }
+            SERVER_PLAYER_STATE => Ok(ServerMessage::PlayerState {
+                player_id: PlayerId(r.u32()?),
+                time: r.f64()?,
+                state: r.player_state()?,
+            }),
+            SERVER_MOVEMENT_ACK => Ok(ServerMessage::MovementAck {
+                tick: r.u32()?,
+                state: r.player_state()?,
+            }),
+            SERVER_PLAYER_LEFT => Ok(ServerMessage::PlayerLeft {
+                player_id: PlayerId(r.u32()?),
+            }),
+            tag => Err(invalid(format!("unknown server message {tag}"))),
+        }
+    }
+}
+
+const MODE_WALK: u8 = 0;
+const MODE_FLY: u8 = 1;
+const MODE_NOCLIP: u8 = 2;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+///! little endian encoding of message fields
+#[derive(Default)]
+pub struct MessageWriter(Vec<u8>);
+
+impl MessageWriter {
+    pub fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, v: f32) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    pub fn f64(&mut self, v: f64) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    pub fn ivec3(&mut self, v: IVec3) {
+        for c in v.to_array() {
+            self.0.extend(c.to_le_bytes());
+        }
+    }
+
+    pub fn vec3(&mut self, v: Vec3) {
+        for c in v.to_array() {
+            self.f32(c);
+        }
+    }
+
+    pub fn quat(&mut self, v: Quat) {
+        for c in v.to_array() {
+            self.f32(c);
+        }
+    }
+
+    pub fn player_state(&mut self, state: &PlayerState) {
+        self.vec3(state.translation);
+        self.vec3(state.velocity);
+        self.quat(state.rotation);
+        self.u8(state.grounded as u8);
+    }
+
+    pub fn movement_mode(&mut self, mode: MovementMode) {
+        self.u8(match mode {
+            MovementMode::Walk => MODE_WALK,
+            MovementMode::Fly { noclip: false } => MODE_FLY,
+            MovementMode::Fly { noclip: true } => MODE_NOCLIP,
+        });
+    }
+
+    ///! the palette and the packed indices as the chunk stores them
+    pub fn chunk_data(&mut self, chunk: &ChunkData) {
+        let palette = chunk.palette();
+        self.u16(palette.len() as u16);
+        for block in palette {
+            self.u16(block.block_type.id());
+        }
+        if palette.len() == 1 {
+            return;
+        }
+        let indices = chunk.indices();
+        self.u8(indices.bits() as u8);
+        for word in indices.words() {
+            self.u64(*word);
+        }
+    }
+}
+
+///! reads back what MessageWriter wrote, errors instead of reading past the end
+pub struct MessageReader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> MessageReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, at: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.at
+    }
+
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let bytes = self
+            .bytes
+            .get(self.at..self.at + N)
+            .ok_or_else(|| invalid("truncated message"))?;
+        self.at += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+
+    pub fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    pub fn ivec3(&mut self) -> io::Result<IVec3> {
+        Ok(IVec3::new(
+            i32::from_le_bytes(self.take()?),
+            i32::from_le_bytes(self.take()?),
+            i32::from_le_bytes(self.take()?),
+        ))
+    }
+
+    pub fn vec3(&mut self) -> io::Result<Vec3> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    pub fn quat(&mut self) -> io::Result<Quat> {
+        let [x, y, z, w] = [self.f32()?, self.f32()?, self.f32()?, self.f32()?];
+        Ok(Quat::from_xyzw(x, y, z, w).normalize())
+    }
+
+    pub fn player_state(&mut self) -> io::Result<PlayerState> {
+        Ok(PlayerState {
+            translation: self.vec3()?,
+            velocity: self.vec3()?,
+            rotation: self.quat()?,
+            grounded: self.u8()? != 0,
+        })
+    }
+
+    pub fn movement_mode(&mut self) -> io::Result<MovementMode> {
+        match self.u8()? {
+            MODE_WALK => Ok(MovementMode::Walk),
+            MODE_FLY => Ok(MovementMode::Fly { noclip: false }),
+            MODE_NOCLIP => Ok(MovementMode::Fly { noclip: true }),
+            mode => Err(invalid(format!("unknown movement mode {mode}"))),
+        }
+    }
+
+    pub fn chunk_data(&mut self) -> io::Result<ChunkData> {
+        let palette_len = self.u16()? as usize;
+        let mut palette = Vec::with_capacity(palette_len.min(self.remaining() / 2));
+        for _ in 0..palette_len {
+            palette.push(BlockData {
+                block_type: BlockType(self.u16()?),
+            });
+        }
+        if palette_len == 1 {
+            return Ok(ChunkData::filled(palette[0].block_type));
+        }
+        let bits = self.u8()? as u32;
+        let word_count = PackedIndices::word_count(CHUNK_SIZE3, bits)
+            .ok_or_else(|| invalid("malformed chunk"))?;
+        let mut words = Vec::with_capacity(word_count.min(self.remaining() / 8));
+        for _ in 0..word_count {
+            words.push(self.u64()?);
+        }
+        PackedIndices::from_words(CHUNK_SIZE3, bits, words)
+            .and_then(|indices| ChunkData::from_packed(palette, indices))
+            .ok_or_else(|| invalid("malformed chunk"))
+    }
+}
+
+///! a non blocking tcp stream exchanging length prefixed messages
+///!
+///! sent messages are buffered until flush, received bytes until they make whole messages
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        // player states are small and late ones are useless
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+            closed: false,
+        })
+    }
+
+    ///! blocks until the server accepted the connection
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::new(TcpStream::connect(addr)?)
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    ///! the peer hung up or the stream failed, nothing will be sent or received anymore
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    ///! bytes sent but not written to the socket yet
+    pub fn pending_bytes(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    pub fn send(&mut self, message: &impl Message) {
+        let mut w = MessageWriter::default();
+        message.encode(&mut w);
+        self.outgoing.extend((w.0.len() as u32).to_le_bytes());
+        self.outgoing.extend(w.0);
+    }
+
+    ///! write as much of what was sent as the socket takes
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let mut written = 0;
+        while written < self.outgoing.len() {
+            match self.stream.write(&self.outgoing[written..]) {
+                Ok(0) => return Err(self.close(io::ErrorKind::WriteZero.into())),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(self.close(e)),
+            }
+        }
+        self.outgoing.drain(..written);
+        Ok(())
+    }
+
+    ///! every whole message received since the last call
+    pub fn receive<M: Message>(&mut self) -> io::Result<Vec<M>> {
+        let mut buf = [0u8; READ_CHUNK_SIZE];
+        while !self.closed {
+            match self.stream.read(&mut buf) {
+                // hung up, the messages it sent before are still handled
+                Ok(0) => self.closed = true,
+                Ok(n) => self.incoming.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(self.close(e)),
+            }
+        }
+
+        let mut messages = Vec::new();
+        let mut at = 0;
+        while let Some(header) = self.incoming.get(at..at + 4) {
+            let len = u32::from_le_bytes(header.try_into().unwrap()) as usize;
+            if len > MAX_MESSAGE_SIZE {
+                return Err(self.close(invalid(format!("message of {len} bytes"))));
+            }
+            let Some(body) = self.incoming.get(at + 4..at + 4 + len) else {
+                break;
+            };
+            let mut r = MessageReader::new(body);
+            match M::decode(&mut r) {
+                Ok(message) if r.remaining() == 0 => messages.push(message),
+                Ok(_) => return Err(self.close(invalid("message has trailing bytes"))),
+                Err(e) => return Err(self.close(e)),
+            }
+            at += 4 + len;
+        }
+        self.incoming.drain(..at);
+        Ok(messages)
+    }
+
+    fn close(&mut self, e: io::Error) -> io::Error {
+        self.closed = true;
+        e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(message: &impl Message) -> Vec<u8> {
+        let mut w = MessageWriter::default();
+        message.encode(&mut w);
+        w.0
+    }
+
+    fn decode<M: Message>(bytes: &[u8]) -> io::Result<M> {
+        let mut r = MessageReader::new(bytes);
+        let message = M::decode(&mut r)?;
+        assert_eq!(r.remaining(), 0, "message has trailing bytes");
+        Ok(message)
+    }
+
+    fn state() -> PlayerState {
+        PlayerState {
+            translation: Vec3::new(1.0, 2.5, -3.0),
+            velocity: Vec3::new(0.0, -9.0, 4.0),
+            rotation: Quat::from_rotation_y(1.0),
+            grounded: true,
+        }
+    }
+
+    ///! a chunk with a few blocks scattered in air, its indices are 2 bits wide
+    fn mixed_chunk() -> ChunkData {
+        ChunkData::from_fn(IVec3::ZERO, |pos| {
+            match (pos.x + pos.y * 3 + pos.z * 7) % 5 {
+                0 => BlockType::GRASS,
+                1 => BlockType::DIRT,
+                _ => BlockType::AIR,
+            }
+        })
+    }
+
+    fn same_chunk(a: &ChunkData, b: &ChunkData) -> bool {
+        (0..CHUNK_SIZE3).all(|i| a.get_block(i).block_type == b.get_block(i).block_type)
+    }
+
+    #[test]
+    fn client_messages_round_trip() {
+        let input = ClientMessage::Input {
+            tick: u32::MAX,
+            input: MovementInput {
+                velocity: Vec3::new(3.0, 0.0, -3.0),
+                jump: true,
+            },
+            mode: MovementMode::Fly { noclip: true },
+            rotation: Quat::from_rotation_x(0.5),
+        };
+        let Ok(ClientMessage::Input {
+            tick,
+            input: decoded,
+            mode,
+            rotation,
+        }) = decode(&encode(&input))
+        else {
+            panic!("expected an input");
+        };
+        assert_eq!(tick, u32::MAX);
+        assert_eq!(decoded.velocity, Vec3::new(3.0, 0.0, -3.0));
+        assert!(decoded.jump);
+        assert_eq!(mode, MovementMode::Fly { noclip: true });
+        assert!(rotation.abs_diff_eq(Quat::from_rotation_x(0.5), 1e-6));
+
+        let set_block = ClientMessage::SetBlock {
+            pos: IVec3::new(-40, 7, 1 << 20),
+            block_type: BlockType::DIRT,
+        };
+        let Ok(ClientMessage::SetBlock { pos, block_type }) = decode(&encode(&set_block)) else {
+            panic!("expected a block edit");
+        };
+        assert_eq!(pos, IVec3::new(-40, 7, 1 << 20));
+        assert_eq!(block_type, BlockType::DIRT);
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        let welcome = ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            player_id: PlayerId(3),
+        };
+        assert!(matches!(
+            decode(&encode(&welcome)),
+            Ok(ServerMessage::Welcome {
+                version: PROTOCOL_VERSION,
+                player_id: PlayerId(3)
+            })
+        ));
+
+        let chunk = ServerMessage::Chunk {
+            chunk_pos: IVec3::new(1, -2, 3),
+            data: Arc::new(mixed_chunk()),
+        };
+        let Ok(ServerMessage::Chunk { chunk_pos, data }) = decode(&encode(&chunk)) else {
+            panic!("expected a chunk");
+        };
+        assert_eq!(chunk_pos, IVec3::new(1, -2, 3));
+        assert!(same_chunk(&data, &mixed_chunk()));
+
+        let forget = ServerMessage::ForgetChunk {
+            chunk_pos: IVec3::NEG_ONE,
+        };
+        assert!(matches!(
+            decode(&encode(&forget)),
+            Ok(ServerMessage::ForgetChunk {
+                chunk_pos: IVec3::NEG_ONE
+            })
+        ));
+
+        let edits = vec![
+            (IVec3::new(1, 2, 3), BlockType::AIR),
+            (IVec3::new(-4, 5, -6), BlockType::GRASS),
+        ];
+        let Ok(ServerMessage::BlockEdits(decoded)) =
+            decode(&encode(&ServerMessage::BlockEdits(edits.clone())))
+        else {
+            panic!("expected block edits");
+        };
+        assert_eq!(decoded, edits);
+
+        let player_state = ServerMessage::PlayerState {
+            player_id: PlayerId(7),
+            time: 12345.678,
+            state: state(),
+        };
+        let Ok(ServerMessage::PlayerState {
+            player_id,
+            time,
+            state: decoded,
+        }) = decode(&encode(&player_state))
+        else {
+            panic!("expected a player state");
+        };
+        assert_eq!(player_id, PlayerId(7));
+        assert_eq!(time, 12345.678);
+        assert_eq!(decoded.translation, state().translation);
+        assert_eq!(decoded.velocity, state().velocity);
+        assert!(decoded.rotation.abs_diff_eq(state().rotation, 1e-6));
+        assert!(decoded.grounded);
+
+        let ack = ServerMessage::MovementAck {
+            tick: 42,
+            state: state(),
+        };
+        let Ok(ServerMessage::MovementAck {
+            tick: 42,
+            state: decoded,
+        }) = decode(&encode(&ack))
+        else {
+            panic!("expected a movement ack");
+        };
+        assert_eq!(decoded.translation, state().translation);
+
+        let left = ServerMessage::PlayerLeft {
+            player_id: PlayerId(9),
+        };
+        assert!(matches!(
+            decode(&encode(&left)),
+            Ok(ServerMessage::PlayerLeft {
+                player_id: PlayerId(9)
+            })
+        ));
+    }
+
+    #[test]
+    fn chunk_data_round_trips() {
+        for chunk in [ChunkData::filled(BlockType::DIRT), mixed_chunk()] {
+            let mut w = MessageWriter::default();
+            w.chunk_data(&chunk);
+            let mut r = MessageReader::new(&w.0);
+            let decoded = r.chunk_data().unwrap();
+            assert_eq!(r.remaining(), 0);
+            assert!(same_chunk(&decoded, &chunk));
+            assert_eq!(decoded.palette().len(), chunk.palette().len());
+        }
+    }
+
+    #[test]
+    fn truncated_messages_are_errors() {
+        let messages = [
+            encode(&ClientMessage::SetBlock {
+                pos: IVec3::ONE,
+                block_type: BlockType::GRASS,
+            }),
+            encode(&ClientMessage::Input {
+                tick: 1,
+                input: MovementInput::default(),
+                mode: MovementMode::Walk,
+                rotation: Quat::IDENTITY,
+            }),
+        ];
+        for bytes in messages {
+            for len in 0..bytes.len() {
+                assert!(decode::<ClientMessage>(&bytes[..len]).is_err());
+            }
+        }
+
+        let chunk = encode(&ServerMessage::Chunk {
+            chunk_pos: IVec3::ZERO,
+            data: Arc::new(mixed_chunk()),
+        });
+        for len in (0..chunk.len()).step_by(97) {
+            assert!(decode::<ServerMessage>(&chunk[..len]).is_err());
+        }
+        let edits = encode(&ServerMessage::BlockEdits(vec![(
+            IVec3::ONE,
+            BlockType::DIRT,
+        )]));
+        for len in 0..edits.len() {
+            assert!(decode::<ServerMessage>(&edits[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn malformed_messages_are_errors() {
+        assert!(decode::<ClientMessage>(&[200]).is_err());
+        assert!(decode::<ServerMessage>(&[200]).is_err());
+
+        // an unknown movement mode
+        let mut input = encode(&ClientMessage::Input {
+            tick: 1,
+            input: MovementInput::default(),
+            mode: MovementMode::Walk,
+            rotation: Quat::IDENTITY,
+        });
+        // tag, tick, velocity and jump come before the mode
+        input[1 + 4 + 12 + 1] = 9;
+        assert!(decode::<ClientMessage>(&input).is_err());
+
+        // a block edit count far larger than the message
+        let mut w = MessageWriter::default();
+        w.u8(SERVER_BLOCK_EDITS);
+        w.u32(u32::MAX);
+        assert!(decode::<ServerMessage>(&w.0).is_err());
+    }
+
+    #[test]
+    fn malformed_chunks_are_errors() {
+        let chunk_with = |palette: &[u16], bits: u8, words: &[u64]| {
+            let mut w = MessageWriter::default();
+            w.u16(palette.len() as u16);
+            for id in palette {
+                w.u16(*id);
+            }
+            w.u8(bits);
+            for word in words {
+                w.u64(*word);
+            }
+            w.0
+        };
+        let read = |bytes: &[u8]| MessageReader::new(bytes).chunk_data();
+
+        // unsupported widths, including those wider than a word
+        for bits in [3, 5, 32, 64, 65, 128, 255] {
+            assert!(read(&chunk_with(&[0, 1], bits, &[])).is_err());
+        }
+        // too narrow for the palette
+        let words = vec![0; CHUNK_SIZE3 / 64];
+        assert!(read(&chunk_with(&[0, 1, 2], 1, &words)).is_err());
+        // an index past the end of the palette
+        let words = vec![u64::MAX; CHUNK_SIZE3 / 32];
+        assert!(read(&chunk_with(&[0, 1, 2], 2, &words)).is_err());
+        // no palette at all
+        assert!(read(&chunk_with(&[], 0, &[])).is_err());
+    }
+
+    #[test]
+    fn oversized_frames_close_the_connection() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut receiver = Connection::new(listener.accept().unwrap().0).unwrap();
+
+        sender
+            .write_all(&(MAX_MESSAGE_SIZE as u32 + 1).to_le_bytes())
+            .unwrap();
+        sender.flush().unwrap();
+        let mut result = Ok(vec![]);
+        for _ in 0..100 {
+            result = receiver.receive::<ClientMessage>();
+            if result.is_err() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert!(result.is_err());
+        assert!(receiver.is_closed());
+    }
+}
@@ -0,0 +1,437 @@
+use std::{
+    collections::VecDeque,
+    io,
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+};
+
+use bevy::{
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+
+use crate::{
+    environment::{
+        block::BlockType,
+        block_registry::BlockRegistry,
+        engine::{Engine, start_modifications},
+        scanner::{RenderDistance, ScannedAreas, Scanner, scanner_chunk_pos},
+        utils::{cli_arg, world_to_chunk},
+        voxel_world::{BlockChanged, VoxelWorld},
+    },
+    player::{
+        creative_mode::REACH,
+        fps_movement::{FPSMovement, MovementInput, MovementMode},
+        player::SPAWN_TRANSLATION,
+    },
+};
+
+use super::protocol::{
+    ClientMessage, Connection, DEFAULT_PORT, PROTOCOL_VERSION, PlayerId, PlayerState, ServerMessage,
+};
+
+///! chunks sent to a client per tick, the closest first
+const MAX_CHUNKS_PER_TICK: usize = 8;
+///! no more chunks are sent to a client while this much is waiting to be written to it
+const MAX_PENDING_BYTES: usize = 1 << 20;
+///! edits further from the player's eyes are refused, with some slack for latency
+const EDIT_REACH: f32 = REACH + 2.0;
+///! inputs waiting to be replayed beyond this are dropped, the client will be corrected
+const MAX_QUEUED_INPUTS: usize = 128;
+///! fixed timesteps a client can save up to catch up after a hiccup, more are lost
+const MAX_SAVED_STEPS: u32 = 8;
+
+///! accepts players and runs the authoritative copy of the world
+pub struct ServerPlugin {
+    ///! address to listen on, `--listen <addr>` or every interface on DEFAULT_PORT
+    pub addr: String,
+}
+
+impl Default for ServerPlugin {
+    fn default() -> Self {
+        Self {
+            addr: cli_arg("--listen").unwrap_or(format!("0.0.0.0:{DEFAULT_PORT}")),
+        }
+    }
+}
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        let server = NetServer::bind(&self.addr)
+            .unwrap_or_else(|e| panic!("failed to listen on {}: {e}", self.addr));
+        if let Ok(addr) = server.local_addr() {
+            info!("listening on {addr}");
+        }
+        app.insert_resource(server);
+        app.add_systems(FixedUpdate, grant_client_steps);
+        app.add_systems(
+            Update,
+            (
+                (accept_clients, receive_client_messages, simulate_clients)
+                    .chain()
+                    .before(start_modifications),
+                (
+                    broadcast_block_changes,
+                    stream_chunks,
+                    broadcast_player_states,
+                    drop_disconnected_clients,
+                    flush_clients,
+                )
+                    .chain()
+                    .after(start_modifications),
+            ),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct NetServer {
+    listener: TcpListener,
+    next_player_id: u32,
+}
+
+impl NetServer {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            next_player_id: 0,
+        })
+    }
+
+    ///! the actual address, when bound to port 0
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+///! a connected player, its entity scans the world around it so its chunks stay loaded
+#[derive(Component)]
+pub struct RemoteClient {
+    pub player_id: PlayerId,
+    connection: Connection,
+    ///! chunks the client holds a copy of, edits to them are forwarded
+    sent_chunks: HashSet<IVec3>,
+    ///! where the server moved it, None until its first input
+    pub state: Option<PlayerState>,
+    ///! received inputs not replayed yet
+    inputs: VecDeque<ClientInput>,
+    ///! fixed timesteps that passed and weren't replayed yet, a client can't move more often
+    steps: u32,
+    ///! tick of the last replayed input, older inputs are dropped
+    last_tick: Option<u32>,
+}
+
+struct ClientInput {
+    tick: u32,
+    input: MovementInput,
+    mode: MovementMode,
+    rotation: Quat,
+}
+
+fn accept_clients(
+    mut commands: Commands,
+    mut server: ResMut<NetServer>,
+    render_distance: Res<RenderDistance>,
+) {
+    loop {
+        let stream = match server.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+            Err(e) => {
+                error!("failed to accept a client: {e}");
+                return;
+            }
+        };
+        let mut connection = match Connection::new(stream) {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to set up a client connection: {e}");
+                continue;
+            }
+        };
+
+        let player_id = PlayerId(server.next_player_id);
+        server.next_player_id += 1;
+        if let Ok(addr) = connection.peer_addr() {
+            info!("player {} joined from {addr}", player_id.0);
+        }
+        connection.send(&ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            player_id,
+        });
+        commands.spawn((
+            RemoteClient {
+                player_id,
+                connection,
+                sent_chunks: HashSet::new(),
+                state: None,
+                inputs: VecDeque::new(),
+                steps: 0,
+                last_tick: None,
+            },
+            // the server moves the player, the client only predicts it
+            FPSMovement {
+                phys_translation: SPAWN_TRANSLATION,
+                prev_phys_translation: SPAWN_TRANSLATION,
+                ..default()
+            },
+            MovementMode::default(),
+            Scanner::data_only(render_distance.0),
+            Transform::from_translation(SPAWN_TRANSLATION),
+        ));
+    }
+}
+
+///! take in player inputs and edit requests, edits are checked before they are queued
+fn receive_client_messages(mut clients: Query<&mut RemoteClient>, mut voxel_world: VoxelWorld) {
+    for mut client in clients.iter_mut() {
+        let messages = match client.connection.receive::<ClientMessage>() {
+            Ok(messages) => messages,
+            Err(e) => {
+                warn!("dropping player {}: {e}", client.player_id.0);
+                continue;
+            }
+        };
+        for message in messages {
+            match message {
+                ClientMessage::Input {
+                    tick,
+                    input,
+                    mode,
+                    rotation,
+                } => {
+                    if client.inputs.len() >= MAX_QUEUED_INPUTS {
+                        continue;
+                    }
+                    client.inputs.push_back(ClientInput {
+                        tick,
+                        input,
+                        mode,
+                        rotation,
+                    });
+                }
+                ClientMessage::SetBlock { pos, block_type } => {
+                    let Some(current) = voxel_world.get_block(pos) else {
+                        continue;
+                    };
+                    let in_reach = client.state.is_some_and(|state| {
+                        state.translation.distance(pos.as_vec3() + 0.5) <= EDIT_REACH
+                    });
+                    let known = block_type == BlockType::AIR
+                        || voxel_world.registry().get(block_type).is_some();
+                    if in_reach && known {
+                        voxel_world.set_block(pos, block_type);
+                    } else {
+                        // put the client's copy back in line
+                        client
+                            .connection
+                            .send(&ServerMessage::BlockEdits(vec![(pos, current)]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+///! every fixed timestep lets each player move one more step
+fn grant_client_steps(mut clients: Query<&mut RemoteClient>) {
+    for mut client in clients.iter_mut() {
+        client.steps = (client.steps + 1).min(MAX_SAVED_STEPS);
+    }
+}
+
+///! replay the inputs of each player one fixed timestep each, as many as the server stepped,
+///! and tell it where it ended up
+fn simulate_clients(
+    fixed_time: Res<Time<Fixed>>,
+    voxel_engine: Res<Engine>,
+    registry: Res<BlockRegistry>,
+    mut clients: Query<(
+        &mut RemoteClient,
+        &mut FPSMovement,
+        &mut MovementMode,
+        &mut Transform,
+    )>,
+) {
+    let dt = fixed_time.timestep().as_secs_f32();
+    for (mut client, mut mov, mut mode, mut transform) in clients.iter_mut() {
+        let mut last = None;
+        while client.steps > 0 {
+            let Some(ClientInput {
+                tick,
+                input,
+                mode: input_mode,
+                rotation,
+            }) = client.inputs.pop_front()
+            else {
+                break;
+            };
+            // ticks wrap around, compare them by difference
+            if client
+                .last_tick
+                .is_some_and(|last_tick| (tick.wrapping_sub(last_tick) as i32) <= 0)
+            {
+                continue;
+            }
+            client.last_tick = Some(tick);
+            client.steps -= 1;
+            // players walk and fly, but don't go through walls
+            let input_mode = match input_mode {
+                MovementMode::Fly { noclip: true } => MovementMode::Fly { noclip: false },
+                input_mode => input_mode,
+            };
+            *mode = input_mode;
+            // nor any faster than the keys move them
+            let input = input.clamped(input_mode);
+            mov.step(input, input_mode, dt, &voxel_engine, &registry);
+            last = Some((tick, rotation));
+        }
+        let Some((tick, rotation)) = last else {
+            continue;
+        };
+
+        let state = PlayerState {
+            translation: mov.phys_translation,
+            velocity: mov.velocity,
+            rotation,
+            grounded: mov.grounded,
+        };
+        // the scanner follows the player
+        transform.translation = state.translation;
+        client.state = Some(state);
+        client
+            .connection
+            .send(&ServerMessage::MovementAck { tick, state });
+    }
+}
+
+///! forward applied edits to the clients holding the edited chunks
+fn broadcast_block_changes(
+    mut block_changed: EventReader<BlockChanged>,
+    mut clients: Query<&mut RemoteClient>,
+) {
+    let mut edits: HashMap<IVec3, Vec<(IVec3, BlockType)>> = HashMap::new();
+    for change in block_changed.read() {
+        let (chunk_pos, _) = world_to_chunk(change.pos);
+        edits
+            .entry(chunk_pos)
+            .or_default()
+            .push((change.pos, change.new));
+    }
+    if edits.is_empty() {
+        return;
+    }
+
+    for mut client in clients.iter_mut() {
+        let client_edits = edits
+            .iter()
+            .filter(|(chunk_pos, _)| client.sent_chunks.contains(*chunk_pos))
+            .flat_map(|(_, chunk_edits)| chunk_edits.iter().copied())
+            .collect::<Vec<_>>();
+        if !client_edits.is_empty() {
+            client
+                .connection
+                .send(&ServerMessage::BlockEdits(client_edits));
+        }
+    }
+}
+
+///! send each client the loaded chunks of its scanned area, and tell it about those that left it
+fn stream_chunks(
+    voxel_engine: Res<Engine>,
+    scanned_areas: Res<ScannedAreas>,
+    mut clients: Query<(Entity, &mut RemoteClient, &GlobalTransform)>,
+) {
+    for (entity, mut client, transform) in clients.iter_mut() {
+        let Some(area) = scanned_areas.0.get(&entity) else {
+            continue;
+        };
+        let RemoteClient {
+            connection,
+            sent_chunks,
+            ..
+        } = client.as_mut();
+
+        let forgotten = sent_chunks
+            .iter()
+            .filter(|chunk_pos| !area.data.contains(*chunk_pos))
+            .copied()
+            .collect::<Vec<_>>();
+        for chunk_pos in forgotten {
+            sent_chunks.remove(&chunk_pos);
+            connection.send(&ServerMessage::ForgetChunk { chunk_pos });
+        }
+
+        let center = scanner_chunk_pos(transform);
+        let mut missing = area
+            .data
+            .iter()
+            .filter(|chunk_pos| !sent_chunks.contains(*chunk_pos))
+            .filter_map(|chunk_pos| Some((*chunk_pos, voxel_engine.world_data.get(chunk_pos)?)))
+            .collect::<Vec<_>>();
+        missing.sort_by_key(|(chunk_pos, _)| chunk_pos.distance_squared(center));
+        for (chunk_pos, chunk_data) in missing.into_iter().take(MAX_CHUNKS_PER_TICK) {
+            if connection.pending_bytes() > MAX_PENDING_BYTES {
+                break;
+            }
+            sent_chunks.insert(chunk_pos);
+            connection.send(&ServerMessage::Chunk {
+                chunk_pos,
+                data: chunk_data.clone(),
+            });
+        }
+    }
+}
+
+///! every player learns where the others are
+fn broadcast_player_states(time: Res<Time>, mut clients: Query<&mut RemoteClient>) {
+    let now = time.elapsed_secs_f64();
+    let states = clients
+        .iter()
+        .filter_map(|client| Some((client.player_id, client.state?)))
+        .collect::<Vec<_>>();
+    for mut client in clients.iter_mut() {
+        for (player_id, state) in states.iter() {
+            if *player_id == client.player_id {
+                continue;
+            }
+            client.connection.send(&ServerMessage::PlayerState {
+                player_id: *player_id,
+                time: now,
+                state: *state,
+            });
+        }
+    }
+}
+
+///! despawning the entity releases the chunks its scanner held
+fn drop_disconnected_clients(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut RemoteClient)>,
+) {
+    let left = clients
+        .iter()
+        .filter(|(_, client)| client.connection.is_closed())
+        .map(|(entity, client)| (entity, client.player_id))
+        .collect::<Vec<_>>();
+    for (entity, player_id) in left.iter() {
+        info!("player {} left", player_id.0);
+        commands.entity(*entity).despawn();
+    }
+    for (_, mut client) in clients.iter_mut() {
+        for (_, player_id) in left.iter() {
+            client.connection.send(&ServerMessage::PlayerLeft {
+                player_id: *player_id,
+            });
+        }
+    }
+}
+
+fn flush_clients(mut clients: Query<&mut RemoteClient>) {
+    for mut client in clients.iter_mut() {
+        if let Err(e) = client.connection.flush() {
+            warn!("dropping player {}: {e}", client.player_id.0);
+        }
+    }
+}
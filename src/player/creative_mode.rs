@@ -4,100 +4,148 @@ use bevy::{
     prelude::*,
 };
 
-use super::fps_camera::FPSCamera;
+use crate::{
+    environment::{block::BlockType, block_registry::BlockRegistry, voxel_world::VoxelWorld},
+    network::client::NetClient,
+};
 
-#[derive(Component)]
-pub struct CreativeMod;
+use super::{
+    fps_camera::FPSCamera,
+    fps_movement::{FPSMovement, MovementMode},
+};
 
-pub fn lay_cube(
-    mouse_input: Res<ButtonInput<MouseButton>>,
-    object_query: Query<&Transform, (With<Cube>, Without<CreativeMod>)>, // Query for objects in the scene
-    player_query: Query<(&mut Transform, &FPSCamera), (With<CreativeMod>, Without<Cube>)>,
-) {
-    if (mouse_input.pressed(MouseButton::Left)) {
-        println!("Trying to lay cube");
-        for (player_transform, _) in player_query.iter() {
-            let origin = player_transform.translation; // Player's position
-            let direction = player_transform.rotation * Vec3::Z; // Forward direction (Z-axis)
+///! how far the player can reach blocks, in world units
+pub const REACH: f32 = 6.0;
 
-            let max_distance = 5.0; // Set the maximum distance for the ray
+///! switches the mouse between editing blocks and shooting
+pub const BUILD_KEY: KeyCode = KeyCode::KeyB;
 
-            // Check for intersections with objects
-            for object_transform in object_query.iter() {
-                if let Some(intersection) = ray_intersects_cube(
-                    origin,
-                    direction,
-                    max_distance,
-                    object_transform.translation,
-                ) {
-                    println!("Hit object at: {:?}", intersection);
-                }
-            }
-        }
-    }
+///! lets the player break and lay blocks
+#[derive(Component)]
+pub struct CreativeMod {
+    ///! the block laid on right click
+    pub selected_block: BlockType,
+    ///! clicks edit blocks, otherwise they are left to the gun
+    pub building: bool,
 }
 
-fn ray_intersects_cube(
-    ray_origin: Vec3,
-    ray_direction: Vec3,
-    max_distance: f32,
-    cube_center: Vec3,
-) -> Option<Vec3> {
-    // Calculate the AABB corners
-    let min = cube_center - CUBE_SIZE / 2.0;
-    let max = cube_center + CUBE_SIZE / 2.0;
-
-    // Initialize t_min and t_max for the intersection
-    let mut t_min = (min.x - ray_origin.x) / ray_direction.x;
-    let mut t_max = (max.x - ray_origin.x) / ray_direction.x;
-
-    if t_min > t_max {
-        std::mem::swap(&mut t_min, &mut t_max);
-    }
-
-    let mut ty_min = (min.y - ray_origin.y) / ray_direction.y;
-    let mut ty_max = (max.y - ray_origin.y) / ray_direction.y;
-
-    if ty_min > ty_max {
-        std::mem::swap(&mut ty_min, &mut ty_max);
+impl Default for CreativeMod {
+    fn default() -> Self {
+        Self {
+            selected_block: BlockType::GRASS,
+            building: true,
+        }
     }
+}
 
-    if (t_min > ty_max) || (ty_min > t_max) {
-        return None; // No intersection
+const BLOCK_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+///! number keys pick the block with that id
+pub fn select_block(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    registry: Res<BlockRegistry>,
+    mut player_query: Query<&mut CreativeMod>,
+) {
+    let Some(id) = BLOCK_KEYS
+        .iter()
+        .position(|key| keyboard_input.just_pressed(*key))
+    else {
+        return;
+    };
+    let block_type = BlockType(id as u16 + 1);
+    let Some(definition) = registry.get(block_type) else {
+        return;
+    };
+    for mut creative in player_query.iter_mut() {
+        creative.selected_block = block_type;
+        info!("selected {}", definition.name);
     }
+}
 
-    // Update t_min and t_max
-    if ty_min > t_min {
-        t_min = ty_min;
-    }
-    if ty_max < t_max {
-        t_max = ty_max;
+///! left click breaks the targeted block, right click lays the selected block against it
+pub fn lay_cube(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut voxel_world: VoxelWorld,
+    player_query: Query<(&GlobalTransform, &CreativeMod), With<FPSCamera>>,
+) {
+    let breaking = mouse_input.just_pressed(MouseButton::Left);
+    let laying = mouse_input.just_pressed(MouseButton::Right);
+    if !breaking && !laying {
+        return;
     }
 
-    let mut tz_min = (min.z - ray_origin.z) / ray_direction.z;
-    let mut tz_max = (max.z - ray_origin.z) / ray_direction.z;
-
-    if tz_min > tz_max {
-        std::mem::swap(&mut tz_min, &mut tz_max);
-    }
+    for (player_transform, creative) in player_query.iter() {
+        if !creative.building {
+            continue;
+        }
+        let origin = player_transform.translation();
+        let direction = player_transform.forward();
+
+        let Some(hit) = voxel_world.raycast(origin, *direction, REACH) else {
+            continue;
+        };
+
+        let (world_pos, block_type) = if breaking {
+            (hit.block_pos, BlockType::AIR)
+        } else {
+            let target = hit.block_pos + hit.normal;
+            // don't bury the player's head
+            if target == origin.floor().as_ivec3() {
+                continue;
+            }
+            (target, creative.selected_block)
+        };
 
-    if (t_min > tz_max) || (tz_min > t_max) {
-        return None; // No intersection
+        voxel_world.set_block(world_pos, block_type);
     }
+}
 
-    // Update t_min and t_max
-    if tz_min > t_min {
-        t_min = tz_min;
+///! BUILD_KEY hands the mouse from the blocks to the gun and back
+pub fn toggle_building(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut player_query: Query<&mut CreativeMod>,
+) {
+    if !keyboard_input.just_pressed(BUILD_KEY) {
+        return;
     }
-    if tz_max < t_max {
-        t_max = tz_max;
+    for mut creative in player_query.iter_mut() {
+        creative.building = !creative.building;
+        info!("building: {}", creative.building);
     }
+}
 
-    // Check if the intersection is within the max distance
-    if t_min < 0.0 || t_min > max_distance {
-        return None; // Intersection is behind the ray origin or beyond max distance
+///! F switches between flying and walking, N toggles noclip while flying and offline,
+///! servers don't let players through walls
+pub fn toggle_movement_mode(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    client: Option<Res<NetClient>>,
+    mut player_query: Query<(&mut MovementMode, &mut FPSMovement), With<CreativeMod>>,
+) {
+    for (mut mode, mut mov) in player_query.iter_mut() {
+        if keyboard_input.just_pressed(KeyCode::KeyF) {
+            *mode = match *mode {
+                MovementMode::Walk => MovementMode::Fly { noclip: false },
+                MovementMode::Fly { .. } => MovementMode::Walk,
+            };
+            // don't keep the flying speed as a jump or a fall
+            mov.velocity.y = 0.0;
+            info!("movement mode: {:?}", *mode);
+        }
+        if keyboard_input.just_pressed(KeyCode::KeyN) && client.is_none() {
+            if let MovementMode::Fly { noclip } = mode.as_mut() {
+                *noclip = !*noclip;
+                info!("movement mode: {:?}", *mode);
+            }
+        }
     }
-
-    // Calculate the intersection point
-    Some(ray_origin + ray_direction * t_min)
 }
@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use bevy::{
+    diagnostic::{DiagnosticPath, DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+
+use crate::environment::{diagnostics::EngineDiagnosticsPlugin, utils::world_to_chunk};
+
+use super::fps_camera::FPSCamera;
+
+///! shows or hides the overlay
+pub const DEBUG_OVERLAY_KEY: KeyCode = KeyCode::F3;
+
+///! on screen engine diagnostics, hidden until DEBUG_OVERLAY_KEY is pressed
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin::default());
+        }
+        app.add_systems(Startup, spawn_debug_overlay);
+        app.add_systems(Update, (toggle_debug_overlay, update_debug_overlay).chain());
+    }
+}
+
+#[derive(Component)]
+pub struct DebugOverlay;
+
+fn spawn_debug_overlay(mut commands: Commands) {
+    commands.spawn((
+        DebugOverlay,
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        Visibility::Hidden,
+    ));
+}
+
+fn toggle_debug_overlay(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut overlay_query: Query<&mut Visibility, With<DebugOverlay>>,
+) {
+    if !keyboard_input.just_pressed(DEBUG_OVERLAY_KEY) {
+        return;
+    }
+    for mut visibility in overlay_query.iter_mut() {
+        visibility.toggle_visible_hidden();
+    }
+}
+
+fn update_debug_overlay(
+    diagnostics: Res<DiagnosticsStore>,
+    player_query: Query<&GlobalTransform, With<FPSCamera>>,
+    mut overlay_query: Query<(&mut Text, &Visibility), With<DebugOverlay>>,
+) {
+    type E = EngineDiagnosticsPlugin;
+    // smoothed value of a diagnostic, 0 until it has been measured
+    let value = |path: &DiagnosticPath| {
+        diagnostics
+            .get(path)
+            .and_then(|diagnostic| diagnostic.smoothed())
+            .unwrap_or(0.0)
+    };
+
+    for (mut text, visibility) in overlay_query.iter_mut() {
+        if visibility == Visibility::Hidden {
+            continue;
+        }
+        let mut s = String::new();
+        let _ = writeln!(
+            s,
+            "fps {:.0} ({:.1}ms)",
+            value(&FrameTimeDiagnosticsPlugin::FPS),
+            value(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        );
+        if let Ok(player_transform) = player_query.single() {
+            let (chunk_pos, _) = world_to_chunk(player_transform.translation().floor().as_ivec3());
+            let _ = writeln!(s, "chunk {} {} {}", chunk_pos.x, chunk_pos.y, chunk_pos.z);
+        }
+        let _ = writeln!(
+            s,
+            "chunks {:.0} loaded, {:.0} meshed",
+            value(&E::LOADED_CHUNKS),
+            value(&E::MESHED_CHUNKS)
+        );
+        let _ = writeln!(
+            s,
+            "data queue +{:.0} -{:.0}, tasks {:.0}",
+            value(&E::DATA_LOAD_QUEUE),
+            value(&E::DATA_UNLOAD_QUEUE),
+            value(&E::DATA_TASKS)
+        );
+        let _ = writeln!(
+            s,
+            "mesh queue +{:.0} -{:.0}, tasks {:.0}",
+            value(&E::MESH_LOAD_QUEUE),
+            value(&E::MESH_UNLOAD_QUEUE),
+            value(&E::MESH_TASKS)
+        );
+        let _ = writeln!(s, "mesh build {:.2}ms", value(&E::MESH_BUILD_TIME));
+        let _ = writeln!(s, "vertices {:.0}", value(&E::VERTICES));
+        let _ = write!(s, "wasted tasks {:.0}", value(&E::WASTED_TASKS));
+        text.0 = s;
+    }
+}
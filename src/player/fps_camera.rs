@@ -31,18 +31,23 @@ pub fn move_camera(
         let delta_yaw = -delta.x * camera.sensitivity;
         let delta_pitch = -delta.y * camera.sensitivity;
 
-        let (yaw, pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
-        let yaw = yaw + delta_yaw;
-
-        // If the pitch was ±¹⁄₂ π, the camera would look straight up or down.
-        // When the user wants to move the camera back to the horizon, which way should the camera face?
-        // The camera has no way of knowing what direction was "forward" before landing in that extreme position,
-        // so the direction picked will for all intents and purposes be arbitrary.
-        // Another issue is that for mathematical reasons, the yaw will effectively be flipped when the pitch is at the extremes.
-        // To not run into these issues, we clamp the pitch to a safe range.
-        const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.01;
-        let pitch = (pitch + delta_pitch).clamp(-PITCH_LIMIT, PITCH_LIMIT);
-
-        transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll);
+        turn(&mut transform, delta_yaw, delta_pitch);
     }
 }
+
+///! turn the view by yaw and pitch radians, without going over the poles
+pub fn turn(transform: &mut Transform, delta_yaw: f32, delta_pitch: f32) {
+    let (yaw, pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
+    let yaw = yaw + delta_yaw;
+
+    // If the pitch was ±¹⁄₂ π, the camera would look straight up or down.
+    // When the user wants to move the camera back to the horizon, which way should the camera face?
+    // The camera has no way of knowing what direction was "forward" before landing in that extreme position,
+    // so the direction picked will for all intents and purposes be arbitrary.
+    // Another issue is that for mathematical reasons, the yaw will effectively be flipped when the pitch is at the extremes.
+    // To not run into these issues, we clamp the pitch to a safe range.
+    const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.01;
+    let pitch = (pitch + delta_pitch).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+
+    transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll);
+}
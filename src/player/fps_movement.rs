@@ -7,6 +7,38 @@ use bevy::{
     prelude::*,
 };
 
+use crate::environment::{block_registry::BlockRegistry, engine::Engine};
+
+/// Downward acceleration, in blocks per second squared.
+pub const GRAVITY: f32 = 28.0;
+/// Vertical speed given by a jump, enough to clear a bit more than one block.
+pub const JUMP_SPEED: f32 = 8.5;
+/// Falling speed is capped so the collision sweep stays short.
+pub const TERMINAL_VELOCITY: f32 = 50.0;
+/// Ledges up to this height are climbed without jumping.
+pub const STEP_HEIGHT: f32 = 1.0;
+/// Half the width of the player's collision box.
+pub const PLAYER_HALF_WIDTH: f32 = 0.3;
+/// Height of the player's collision box.
+pub const PLAYER_HEIGHT: f32 = 1.8;
+/// Distance from the feet to the camera, `phys_translation` is the eye position.
+pub const EYE_HEIGHT: f32 = 1.62;
+/// Speed the movement keys ask for, in blocks per second.
+pub const SPEED: f32 = 2.0;
+/// Holding Shift multiplies the speed by this.
+pub const SPRINT_FACTOR: f32 = 2.0;
+
+/// How the player moves through the world.
+#[derive(Component, Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MovementMode {
+    /// No gravity, Space and Ctrl move up and down.
+    /// With `noclip`, blocks don't stop the player either.
+    Fly { noclip: bool },
+    /// Collides with blocks and falls, Space jumps.
+    #[default]
+    Walk,
+}
+
 #[derive(Component, Default)]
 pub struct FPSMovement {
     /// A vector representing the player's input, accumulated over all frames that ran
@@ -24,6 +56,151 @@ pub struct FPSMovement {
     /// The value [`PhysicalTranslation`] had in the last fixed timestep.
     /// Used for interpolation in the `interpolate_rendered_transform` system.
     pub prev_phys_translation: Vec3,
+    /// Whether the player stood on a solid block at the end of the last fixed timestep.
+    pub grounded: bool,
+    /// Set when jump was pressed since the last fixed timestep.
+    pub jump: bool,
+}
+
+/// What the player asked for during one fixed timestep.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MovementInput {
+    /// Wished velocity, only the horizontal part is used while walking.
+    pub velocity: Vec3,
+    pub jump: bool,
+}
+
+impl MovementInput {
+    /// The same input with its wished velocity limited to what `handle_fps_movement` can ask for,
+    /// so the server doesn't move a player faster than the keys would.
+    pub fn clamped(self, mode: MovementMode) -> Self {
+        if !self.velocity.is_finite() {
+            return Self {
+                velocity: Vec3::ZERO,
+                ..self
+            };
+        }
+        let max_speed = SPEED * SPRINT_FACTOR;
+        let horizontal = self.velocity.xz().clamp_length_max(max_speed);
+        let vertical = match mode {
+            MovementMode::Fly { .. } => self.velocity.y.clamp(-max_speed, max_speed),
+            MovementMode::Walk => 0.0,
+        };
+        Self {
+            velocity: Vec3::new(horizontal.x, vertical, horizontal.y),
+            ..self
+        }
+    }
+}
+
+impl FPSMovement {
+    /// The player's collision box around a given eye position.
+    pub fn aabb(eye: Vec3) -> (Vec3, Vec3) {
+        let min = eye - Vec3::new(PLAYER_HALF_WIDTH, EYE_HEIGHT, PLAYER_HALF_WIDTH);
+        let max = min
+            + Vec3::new(
+                2.0 * PLAYER_HALF_WIDTH,
+                PLAYER_HEIGHT,
+                2.0 * PLAYER_HALF_WIDTH,
+            );
+        (min, max)
+    }
+
+    /// The input the next fixed timestep will consume, as `handle_fps_movement` left it.
+    pub fn input(&self) -> MovementInput {
+        MovementInput {
+            velocity: self.velocity,
+            jump: self.jump,
+        }
+    }
+
+    /// Advance the simulation by one fixed timestep of `dt` seconds, driven by `input`.
+    /// Replaying the same inputs from the same state gives the same positions,
+    /// which is what lets the client predict its movement and the server check it.
+    pub fn step(
+        &mut self,
+        input: MovementInput,
+        mode: MovementMode,
+        dt: f32,
+        engine: &Engine,
+        registry: &BlockRegistry,
+    ) {
+        self.velocity.x = input.velocity.x;
+        self.velocity.z = input.velocity.z;
+        if let MovementMode::Fly { .. } = mode {
+            self.velocity.y = input.velocity.y;
+        }
+        self.jump = input.jump;
+        self.prev_phys_translation = self.phys_translation;
+
+        match mode {
+            MovementMode::Fly { noclip: true } => {
+                let motion = self.velocity * dt;
+                self.phys_translation += motion;
+                self.grounded = false;
+                self.acc_input = Vec2::ZERO;
+                return;
+            }
+            MovementMode::Fly { noclip: false } => {
+                let motion = self.velocity * dt;
+                let (min, max) = FPSMovement::aabb(self.phys_translation);
+                let applied = engine.move_aabb(registry, min, max, motion);
+                self.phys_translation += applied;
+                self.grounded = false;
+                self.acc_input = Vec2::ZERO;
+                return;
+            }
+            MovementMode::Walk => (),
+        }
+
+        if self.jump && self.grounded {
+            self.velocity.y = JUMP_SPEED;
+        }
+        self.velocity.y = (self.velocity.y - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+
+        let motion = self.velocity * dt;
+        let (min, max) = FPSMovement::aabb(self.phys_translation);
+        let mut applied = engine.move_aabb(registry, min, max, motion);
+
+        // blocked by a wall while walking, try climbing it as a ledge:
+        // up by the step height, across, then back down onto it
+        let blocked = applied.xz().distance_squared(motion.xz()) > 1e-6;
+        if self.grounded && blocked {
+            let up = engine.sweep_aabb_axis(registry, min, max, 1, STEP_HEIGHT);
+            let raised = Vec3::Y * up;
+            let across = engine.move_aabb(
+                registry,
+                min + raised,
+                max + raised,
+                Vec3::new(motion.x, 0.0, motion.z),
+            );
+            let down = engine.sweep_aabb_axis(
+                registry,
+                min + raised + across,
+                max + raised + across,
+                1,
+                -up,
+            );
+            let stepped = raised + across + Vec3::Y * down;
+            if stepped.xz().length_squared() > applied.xz().length_squared() {
+                applied = stepped;
+            }
+        }
+
+        // landed or bumped our head
+        if (applied.y - motion.y).abs() > 1e-6 {
+            self.velocity.y = 0.0;
+        }
+        self.phys_translation += applied;
+
+        // probe just below the feet
+        let (min, max) = FPSMovement::aabb(self.phys_translation);
+        self.grounded = engine.sweep_aabb_axis(registry, min, max, 1, -0.05) > -0.05;
+
+        // Reset the input accumulator, as we are currently consuming all input that happened since the last fixed timestep.
+        self.acc_input = Vec2::ZERO;
+        self.jump = false;
+    }
 }
 
 /// Handle keyboard input and accumulate it in the `AccumulatedInput` component.
@@ -32,10 +209,9 @@ pub struct FPSMovement {
 /// This is a very simple one: we just accumulate the input and average it out by normalizing it.
 pub fn handle_fps_movement(
     keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut query: Query<(&Transform, &mut FPSMovement)>,
+    mut query: Query<(&Transform, &mut FPSMovement, &MovementMode)>,
 ) {
-    const SPEED: f32 = 2.0;
-    for (transform, mut mov) in query.iter_mut() {
+    for (transform, mut mov, mode) in query.iter_mut() {
         let forward = -Vec2::new(transform.forward().x, transform.forward().z);
         let right = Vec2::new(transform.forward().z, -transform.forward().x);
 
@@ -61,10 +237,31 @@ pub fn handle_fps_movement(
         let mut normalized = mov.acc_input.extend(0.0).normalize_or_zero() * SPEED;
 
         if (keyboard_input.pressed(KeyCode::ShiftLeft)) {
-            normalized *= 2.0;
+            normalized *= SPRINT_FACTOR;
         }
         mov.velocity.x = normalized.x;
         mov.velocity.z = normalized.y;
+
+        match mode {
+            MovementMode::Fly { .. } => {
+                let mut vertical = 0.0;
+                if keyboard_input.pressed(KeyCode::Space) {
+                    vertical += 1.0;
+                }
+                if keyboard_input.pressed(KeyCode::ControlLeft) {
+                    vertical -= 1.0;
+                }
+                if keyboard_input.pressed(KeyCode::ShiftLeft) {
+                    vertical *= SPRINT_FACTOR;
+                }
+                mov.velocity.y = vertical * SPEED;
+            }
+            MovementMode::Walk => {
+                if keyboard_input.pressed(KeyCode::Space) {
+                    mov.jump = true;
+                }
+            }
+        }
     }
 }
 
@@ -89,16 +286,14 @@ pub fn interpolate_fps_movement(
 /// We are being explicit here for clarity.
 pub fn advance_fps_movement(
     fixed_time: Res<Time<Fixed>>,
-    mut query: Query<(&mut FPSMovement, &Camera)>,
+    voxel_engine: Res<Engine>,
+    registry: Res<BlockRegistry>,
+    mut query: Query<(&mut FPSMovement, &MovementMode, &Camera)>,
 ) {
-    for (mut mov, camera) in query.iter_mut() {
+    let dt = fixed_time.delta_secs();
+    for (mut mov, mode, camera) in query.iter_mut() {
         camera.hdr;
-        mov.prev_phys_translation = mov.phys_translation;
-        let new_translation = mov.velocity * fixed_time.delta_secs();
-        // println!("{}", new_translation);
-        mov.phys_translation += new_translation;
-
-        // Reset the input accumulator, as we are currently consuming all input that happened since the last fixed timestep.
-        mov.acc_input = Vec2::ZERO;
+        let input = mov.input();
+        mov.step(input, *mode, dt, &voxel_engine, &registry);
     }
 }
@@ -0,0 +1,403 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    color::palettes::tailwind, pbr::NotShadowCaster, prelude::*, render::view::RenderLayers,
+};
+use rand::Rng;
+
+use crate::environment::{
+    block::BlockType, block_registry::BlockRegistry, engine::Engine, raycast::ray_aabb,
+    utils::assets_path,
+};
+
+use super::{
+    creative_mode::CreativeMod,
+    fps_camera::{FPSCamera, turn},
+    gun_definition::{FireMode, GunDefinition, GunDefinitionHandle, GunDefinitionLoader},
+};
+
+///! reloads the held gun before its magazine is empty
+pub const RELOAD_KEY: KeyCode = KeyCode::KeyR;
+///! writes the held gun's definition back to its file
+pub const SAVE_GUN_KEY: KeyCode = KeyCode::F5;
+
+///! where the barrel ends, relative to the gun
+const MUZZLE_OFFSET: Vec3 = Vec3::new(0.0, 0.0, -0.3);
+///! seconds the muzzle flash stays
+const MUZZLE_FLASH_TIME: f32 = 0.05;
+///! seconds impact markers stay
+const IMPACT_MARKER_TIME: f32 = 5.0;
+///! impact markers are lifted off the surface so they don't sink into it
+const IMPACT_MARKER_OFFSET: f32 = 0.02;
+
+///! hitscan guns, their ShotHit events and the effects of their shots
+pub struct GunPlugin;
+
+impl Plugin for GunPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<GunDefinition>();
+        app.init_asset_loader::<GunDefinitionLoader>();
+        app.add_event::<ShotHit>();
+        app.init_resource::<ShotEffects>();
+        app.add_systems(
+            Update,
+            (
+                apply_gun_definitions,
+                reload_guns,
+                fire_guns,
+                spawn_impact_markers,
+                expire_shot_effects,
+            )
+                .chain(),
+        );
+        app.add_systems(Update, save_gun_definitions);
+    }
+}
+
+///! a hitscan gun, the child of a player, fired from its eyes with the left mouse button
+#[derive(Component, Clone, Debug)]
+pub struct Gun {
+    pub definition: GunDefinition,
+    ///! shots left in the magazine
+    pub ammo: u32,
+    ///! seconds until the magazine is full again, None when not reloading
+    pub reloading: Option<f32>,
+    ///! seconds until the next shot
+    cooldown: f32,
+    ///! shots left in the current burst
+    burst_left: u32,
+    ///! shots since the trigger was pulled, indexes the recoil pattern
+    streak: usize,
+}
+
+impl Default for Gun {
+    fn default() -> Self {
+        Self::new(GunDefinition::default())
+    }
+}
+
+impl Gun {
+    ///! a gun with a full magazine
+    pub fn new(definition: GunDefinition) -> Self {
+        Self {
+            ammo: definition.magazine_size,
+            definition,
+            reloading: None,
+            cooldown: 0.0,
+            burst_left: 0,
+            streak: 0,
+        }
+    }
+
+    fn start_reload(&mut self) {
+        if self.reloading.is_none() && self.ammo < self.definition.magazine_size {
+            self.reloading = Some(self.definition.reload_time);
+            self.burst_left = 0;
+        }
+    }
+}
+
+///! a box around the entity's translation that shots can hit
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Hitbox {
+    pub half_extents: Vec3,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum ShotTarget {
+    Block {
+        block_pos: IVec3,
+        block_type: BlockType,
+    },
+    ///! an entity with a Hitbox
+    Player(Entity),
+}
+
+///! the closest thing a shot hit, shots hitting nothing within range send nothing
+#[derive(Event, Copy, Clone, Debug)]
+pub struct ShotHit {
+    ///! the player holding the gun
+    pub shooter: Entity,
+    pub target: ShotTarget,
+    ///! where the shot landed, in world space
+    pub point: Vec3,
+    ///! normal of the surface that was hit, zero if the shot started inside it
+    pub normal: Vec3,
+    pub distance: f32,
+    pub damage: f32,
+}
+
+#[derive(Resource)]
+struct ShotEffects {
+    flash_mesh: Handle<Mesh>,
+    flash_material: Handle<StandardMaterial>,
+    impact_mesh: Handle<Mesh>,
+    block_impact_material: Handle<StandardMaterial>,
+    player_impact_material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for ShotEffects {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let flash_mesh = meshes.add(Sphere::new(0.04));
+        let impact_mesh = meshes.add(Cuboid::from_length(0.06));
+
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let mut glowing = |color: Color| {
+            materials.add(StandardMaterial {
+                base_color: color,
+                emissive: color.into(),
+                unlit: true,
+                ..default()
+            })
+        };
+        Self {
+            flash_mesh,
+            flash_material: glowing(Color::from(tailwind::AMBER_200)),
+            impact_mesh,
+            block_impact_material: glowing(Color::from(tailwind::STONE_900)),
+            player_impact_material: glowing(Color::from(tailwind::RED_600)),
+        }
+    }
+}
+
+///! despawned once the timer finishes
+#[derive(Component)]
+struct ShotEffect(Timer);
+
+///! use the definitions of the guns whose file was (re)loaded
+fn apply_gun_definitions(
+    mut events: EventReader<AssetEvent<GunDefinition>>,
+    definitions: Res<Assets<GunDefinition>>,
+    asset_server: Res<AssetServer>,
+    mut guns: Query<(&mut Gun, &GunDefinitionHandle, Option<&mut Mesh3d>)>,
+) {
+    for event in events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event
+        else {
+            continue;
+        };
+        let Some(definition) = definitions.get(*id) else {
+            continue;
+        };
+        for (mut gun, handle, mesh) in guns.iter_mut() {
+            if handle.0.id() != *id {
+                continue;
+            }
+            // a smaller magazine can't hold what was left in the old one
+            let ammo = gun.ammo.min(definition.magazine_size);
+            *gun = Gun {
+                ammo,
+                ..Gun::new(definition.clone())
+            };
+            if let (Some(path), Some(mut mesh)) = (&definition.view_model, mesh) {
+                mesh.0 = asset_server.load(path.clone());
+            }
+            info!("loaded gun {}", definition.name);
+        }
+    }
+}
+
+///! RELOAD_KEY or an empty magazine start a reload, which refills the magazine once it's over
+fn reload_guns(
+    time: Res<Time>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut guns: Query<&mut Gun>,
+) {
+    for mut gun in guns.iter_mut() {
+        if keyboard_input.just_pressed(RELOAD_KEY) || gun.ammo == 0 {
+            gun.start_reload();
+        }
+        let Some(left) = gun.reloading else {
+            continue;
+        };
+        let left = left - time.delta_secs();
+        if left > 0.0 {
+            gun.reloading = Some(left);
+        } else {
+            gun.reloading = None;
+            gun.ammo = gun.definition.magazine_size;
+        }
+    }
+}
+
+///! fire the guns whose trigger is pulled and cooldown is over, as their fire mode allows
+///! each shot scatters within the spread, hits the closest block or hitbox and kicks the view
+fn fire_guns(
+    mut commands: Commands,
+    time: Res<Time>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    voxel_engine: Res<Engine>,
+    registry: Res<BlockRegistry>,
+    effects: Res<ShotEffects>,
+    mut guns: Query<(Entity, &mut Gun, &ChildOf, Option<&RenderLayers>)>,
+    mut players: Query<(&mut Transform, &GlobalTransform, Option<&CreativeMod>), With<FPSCamera>>,
+    hitboxes: Query<(Entity, &GlobalTransform, &Hitbox)>,
+    mut shot_hits: EventWriter<ShotHit>,
+) {
+    for (gun_entity, mut gun, child_of, render_layers) in guns.iter_mut() {
+        gun.cooldown -= time.delta_secs();
+        let shooter = child_of.parent();
+        let Ok((mut player_transform, player_global, creative)) = players.get_mut(shooter) else {
+            continue;
+        };
+        // the mouse edits blocks while building
+        let building = creative.is_some_and(|creative| creative.building);
+        let held = !building && mouse_input.pressed(MouseButton::Left);
+        let pulled = !building && mouse_input.just_pressed(MouseButton::Left);
+
+        let firing = match gun.definition.fire_mode {
+            FireMode::Semi => pulled,
+            FireMode::Auto => held,
+            FireMode::Burst { shots } => {
+                if pulled && gun.burst_left == 0 {
+                    gun.burst_left = shots;
+                }
+                gun.burst_left > 0
+            }
+        };
+        if !held && gun.burst_left == 0 {
+            gun.streak = 0;
+        }
+        if !firing || gun.reloading.is_some() || gun.ammo == 0 {
+            gun.cooldown = gun.cooldown.max(0.0);
+            continue;
+        }
+        if gun.cooldown > 0.0 {
+            continue;
+        }
+        // carrying the remainder over keeps the rate steady at low frame rates
+        gun.cooldown += 1.0 / gun.definition.fire_rate.max(f32::EPSILON);
+        gun.ammo -= 1;
+        gun.burst_left = gun.burst_left.saturating_sub(1);
+
+        let mut flash = commands.spawn((
+            Mesh3d(effects.flash_mesh.clone()),
+            MeshMaterial3d(effects.flash_material.clone()),
+            Transform::from_translation(MUZZLE_OFFSET),
+            NotShadowCaster,
+            ShotEffect(Timer::from_seconds(MUZZLE_FLASH_TIME, TimerMode::Once)),
+            ChildOf(gun_entity),
+        ));
+        // drawn with the gun, by the view model camera
+        if let Some(render_layers) = render_layers {
+            flash.insert(render_layers.clone());
+        }
+
+        let origin = player_global.translation();
+        let direction = scatter(player_global.rotation(), gun.definition.spread);
+        let range = gun.definition.range;
+
+        let block_hit = voxel_engine
+            .raycast(&registry, origin, direction, range)
+            .map(|hit| {
+                let target = ShotTarget::Block {
+                    block_pos: hit.block_pos,
+                    block_type: hit.block.block_type,
+                };
+                (target, hit.distance, hit.normal.as_vec3())
+            });
+        let player_hit = hitboxes
+            .iter()
+            .filter(|(entity, _, _)| *entity != shooter)
+            .filter_map(|(entity, transform, hitbox)| {
+                let center = transform.translation();
+                let (distance, normal) = ray_aabb(
+                    origin,
+                    direction,
+                    center - hitbox.half_extents,
+                    center + hitbox.half_extents,
+                )?;
+                Some((ShotTarget::Player(entity), distance, normal))
+            })
+            .filter(|(_, distance, _)| *distance <= range)
+            .min_by(|(_, a, _), (_, b, _)| a.total_cmp(b));
+
+        let closest = [block_hit, player_hit]
+            .into_iter()
+            .flatten()
+            .min_by(|(_, a, _), (_, b, _)| a.total_cmp(b));
+        if let Some((target, distance, normal)) = closest {
+            shot_hits.write(ShotHit {
+                shooter,
+                target,
+                point: origin + direction * distance,
+                normal,
+                distance,
+                damage: gun.definition.damage_at(distance),
+            });
+        }
+
+        // the shot left along the old view, the next one follows the kick
+        let kick = gun.definition.recoil(gun.streak);
+        gun.streak += 1;
+        turn(&mut player_transform, -kick.x, kick.y);
+    }
+}
+
+///! a direction within spread radians of the looking direction of rotation
+fn scatter(rotation: Quat, spread: f32) -> Vec3 {
+    let mut rng = rand::rng();
+    // the square root spreads shots evenly over the cone's opening instead of bunching them in the middle
+    let angle = spread * rng.random::<f32>().sqrt();
+    let around = rng.random_range(0.0..TAU);
+    let local = Vec3::new(
+        angle.sin() * around.cos(),
+        angle.sin() * around.sin(),
+        -angle.cos(),
+    );
+    rotation * local
+}
+
+///! mark where the shots landed, players in another color than blocks
+fn spawn_impact_markers(
+    mut commands: Commands,
+    effects: Res<ShotEffects>,
+    mut shot_hits: EventReader<ShotHit>,
+) {
+    for hit in shot_hits.read() {
+        let material = match hit.target {
+            ShotTarget::Block { .. } => effects.block_impact_material.clone(),
+            ShotTarget::Player(_) => effects.player_impact_material.clone(),
+        };
+        commands.spawn((
+            Mesh3d(effects.impact_mesh.clone()),
+            MeshMaterial3d(material),
+            Transform::from_translation(hit.point + hit.normal * IMPACT_MARKER_OFFSET),
+            NotShadowCaster,
+            ShotEffect(Timer::from_seconds(IMPACT_MARKER_TIME, TimerMode::Once)),
+        ));
+    }
+}
+
+fn expire_shot_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut effects: Query<(Entity, &mut ShotEffect)>,
+) {
+    for (entity, mut effect) in effects.iter_mut() {
+        if effect.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+///! SAVE_GUN_KEY writes the definitions of the held guns back to their files in the assets folder
+fn save_gun_definitions(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    guns: Query<(&Gun, &GunDefinitionHandle)>,
+) {
+    if !keyboard_input.just_pressed(SAVE_GUN_KEY) {
+        return;
+    }
+    for (gun, handle) in guns.iter() {
+        let Some(asset_path) = handle.0.path() else {
+            continue;
+        };
+        let path = assets_path().join(asset_path.path());
+        match gun.definition.save(&path) {
+            Ok(()) => info!("saved gun {} to {}", gun.definition.name, path.display()),
+            Err(e) => error!("failed to save gun to {}: {e}", path.display()),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::{error::Error, fs, io, path::Path};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+///! the gun the player spawns with, relative to the assets folder
+pub const DEFAULT_GUN_PATH: &str = "guns/rifle.gun.ron";
+
+///! what holding the trigger does
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FireMode {
+    ///! one shot per click
+    #[default]
+    Semi,
+    ///! shoots as long as the trigger is held
+    Auto,
+    ///! a click fires this many shots, at the fire rate
+    Burst { shots: u32 },
+}
+
+///! everything that makes a gun, read from a `.gun.ron` or `.gun.json` file
+#[derive(Asset, TypePath, Clone, Debug, Serialize, Deserialize)]
+pub struct GunDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub fire_mode: FireMode,
+    ///! shots per second
+    pub fire_rate: f32,
+    ///! shots before reloading
+    pub magazine_size: u32,
+    ///! seconds a reload takes
+    pub reload_time: f32,
+    ///! damage of a shot at point blank
+    pub damage: f32,
+    ///! (distance, fraction of damage) points, blended linearly between them
+    ///! and held before the first and after the last, no points is no falloff
+    #[serde(default)]
+    pub damage_falloff: Vec<(f32, f32)>,
+    ///! how far shots reach, in world units
+    pub range: f32,
+    ///! half angle of the cone shots scatter in, in radians
+    #[serde(default)]
+    pub spread: f32,
+    ///! (right, up) kick of the view in radians for each shot of a streak,
+    ///! the last one repeats for longer streaks
+    #[serde(default)]
+    pub recoil_pattern: Vec<(f32, f32)>,
+    ///! mesh drawn in the player's hand, an asset path like "models/rifle.glb#Mesh0/Primitive0"
+    ///! None keeps the default arm
+    #[serde(default)]
+    pub view_model: Option<String>,
+}
+
+impl Default for GunDefinition {
+    ///! the built-in gun, used until its file is loaded
+    fn default() -> Self {
+        Self {
+            name: "rifle".into(),
+            fire_mode: FireMode::Auto,
+            fire_rate: 8.0,
+            magazine_size: 30,
+            reload_time: 1.5,
+            damage: 20.0,
+            damage_falloff: vec![],
+            range: 100.0,
+            spread: 0.01,
+            recoil_pattern: vec![],
+            view_model: None,
+        }
+    }
+}
+
+impl GunDefinition {
+    ///! damage of a shot that hit at distance, after falloff
+    pub fn damage_at(&self, distance: f32) -> f32 {
+        let Some(((first_distance, first), (last_distance, last))) =
+            self.damage_falloff.first().zip(self.damage_falloff.last())
+        else {
+            return self.damage;
+        };
+        let fraction = if distance <= *first_distance {
+            *first
+        } else if distance >= *last_distance {
+            *last
+        } else {
+            self.damage_falloff
+                .windows(2)
+                .find(|points| distance < points[1].0)
+                .map_or(*last, |points| {
+                    let ((d0, f0), (d1, f1)) = (points[0], points[1]);
+                    f0 + (f1 - f0) * (distance - d0) / (d1 - d0).max(f32::EPSILON)
+                })
+        };
+        self.damage * fraction.max(0.0)
+    }
+
+    ///! (right, up) kick of the nth shot of a streak
+    pub fn recoil(&self, shot: usize) -> Vec2 {
+        let last = self.recoil_pattern.len().saturating_sub(1);
+        self.recoil_pattern
+            .get(shot.min(last))
+            .map_or(Vec2::ZERO, |(right, up)| Vec2::new(*right, *up))
+    }
+
+    ///! parse a definition, as json if the path ends in .json and as ron otherwise
+    pub fn from_bytes(bytes: &[u8], path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if is_json(path) {
+            Ok(serde_json::from_slice(bytes)?)
+        } else {
+            Ok(ron::de::from_bytes(bytes)?)
+        }
+    }
+
+    ///! write the definition to a file, in the format its extension asks for
+    ///! a loaded definition saved in the assets folder is reloaded with hot_reload
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = if is_json(path) {
+            serde_json::to_string_pretty(self).map_err(io::Error::other)?
+        } else {
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(io::Error::other)?
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text + "\n")
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+#[derive(Default)]
+pub struct GunDefinitionLoader;
+
+impl AssetLoader for GunDefinitionLoader {
+    type Asset = GunDefinition;
+    type Settings = ();
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        GunDefinition::from_bytes(&bytes, load_context.path())
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["gun.ron", "gun.json"]
+    }
+}
+
+///! the file a gun's definition comes from
+#[derive(Component)]
+pub struct GunDefinitionHandle(pub Handle<GunDefinition>);
@@ -1,4 +1,8 @@
-// pub mod creative_mode;
+pub mod creative_mode;
+pub mod debug_overlay;
 pub mod fps_camera;
 pub mod fps_movement;
+pub mod gun;
+pub mod gun_definition;
 pub mod player;
+pub mod remote_player;
@@ -1,8 +1,8 @@
 use bevy::{
-    asset::Assets,
+    asset::{AssetServer, Assets},
     color::{Color, palettes::tailwind},
     core_pipeline::core_3d::Camera3d,
-    ecs::system::{Commands, ResMut},
+    ecs::system::{Commands, Res, ResMut},
     math::{
         Vec3,
         primitives::{Cuboid, Sphere},
@@ -17,9 +17,19 @@ use bevy::{
     utils::default,
 };
 
-use super::{fps_camera::FPSCamera, fps_movement::FPSMovement};
+use crate::environment::scanner::{RenderDistance, Scanner};
+
+use super::{
+    creative_mode::CreativeMod,
+    fps_camera::FPSCamera,
+    fps_movement::{FPSMovement, MovementMode},
+    gun::Gun,
+    gun_definition::{DEFAULT_GUN_PATH, GunDefinitionHandle},
+};
 
 const DEFAULT_SENSITIVITY: f32 = 0.003;
+/// Where players appear, as the eye position of their `FPSMovement`.
+pub const SPAWN_TRANSLATION: Vec3 = Vec3::new(-5.0, 1.80, 0.0);
 /// Used by the view model camera and the player's arm.
 /// The light source belongs to both layers.
 const VIEW_MODEL_RENDER_LAYER: usize = 1;
@@ -28,6 +38,8 @@ pub fn create_player(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    render_distance: Res<RenderDistance>,
+    asset_server: Res<AssetServer>,
 ) {
     // TODO: something better than just a cuboid
     let arm = meshes.add(Cuboid::new(0.1, 0.1, 0.5));
@@ -42,12 +54,16 @@ pub fn create_player(
             Transform::default(),
             FPSMovement {
                 //prev_phys_translation: Vec3::new(-5.0, 1.80, -5.0),
-                phys_translation: Vec3::new(-5.0, 1.80, 0.0),
+                phys_translation: SPAWN_TRANSLATION,
                 ..default()
             },
             FPSCamera {
                 sensitivity: DEFAULT_SENSITIVITY,
             },
+            CreativeMod::default(),
+            MovementMode::Walk,
+            // chunks are streamed around the player
+            Scanner::new(render_distance.0),
             Camera { ..default() },
             Camera3d { ..default() },
             Projection::from(PerspectiveProjection {
@@ -77,6 +93,9 @@ pub fn create_player(
                 Mesh3d(arm),
                 MeshMaterial3d(arm_material),
                 Transform::from_xyz(0.2, -0.1, -0.25),
+                // the built-in gun until its file is loaded
+                Gun::default(),
+                GunDefinitionHandle(asset_server.load(DEFAULT_GUN_PATH)),
                 // Ensure the arm is only rendered by the view model camera.
                 RenderLayers::layer(VIEW_MODEL_RENDER_LAYER),
                 // The arm is free-floating, so shadows would look weird.
@@ -0,0 +1,73 @@
+use bevy::{color::palettes::tailwind, prelude::*};
+
+use crate::network::{
+    interpolation::{InterpolationDelay, RemotePlayers},
+    protocol::PlayerId,
+};
+
+use super::{
+    fps_movement::{EYE_HEIGHT, PLAYER_HALF_WIDTH, PLAYER_HEIGHT},
+    gun::Hitbox,
+};
+
+///! the body of another player on the server
+#[derive(Component)]
+pub struct RemotePlayer(pub PlayerId);
+
+///! spawn, move and despawn the bodies of the other players to match what the server sent,
+///! blended between its snapshots a little in the past
+pub fn sync_remote_players(
+    mut commands: Commands,
+    time: Res<Time>,
+    remote_players: Option<Res<RemotePlayers>>,
+    delay: Option<Res<InterpolationDelay>>,
+    mut bodies: Query<(Entity, &RemotePlayer, &mut Transform)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let (Some(remote_players), Some(delay)) = (remote_players, delay) else {
+        return;
+    };
+    let Some(render_time) = remote_players.render_time(time.elapsed_secs_f64(), *delay) else {
+        return;
+    };
+
+    let body_transform = |translation: Vec3, rotation: Quat| {
+        // only turn around the vertical axis, the head isn't modelled
+        let (yaw, _, _) = rotation.to_euler(EulerRot::YXZ);
+        Transform::from_translation(translation - Vec3::Y * (EYE_HEIGHT - PLAYER_HEIGHT / 2.0))
+            .with_rotation(Quat::from_rotation_y(yaw))
+    };
+
+    let mut spawned = Vec::new();
+    for (entity, RemotePlayer(player_id), mut transform) in bodies.iter_mut() {
+        match remote_players.sample(*player_id, render_time) {
+            Some(state) => *transform = body_transform(state.translation, state.rotation),
+            None => commands.entity(entity).despawn(),
+        }
+        spawned.push(*player_id);
+    }
+
+    for player_id in remote_players.player_ids() {
+        if spawned.contains(&player_id) {
+            continue;
+        }
+        let Some(state) = remote_players.sample(player_id, render_time) else {
+            continue;
+        };
+        commands.spawn((
+            RemotePlayer(player_id),
+            Mesh3d(meshes.add(Cuboid::new(
+                2.0 * PLAYER_HALF_WIDTH,
+                PLAYER_HEIGHT,
+                2.0 * PLAYER_HALF_WIDTH,
+            ))),
+            MeshMaterial3d(materials.add(Color::from(tailwind::ORANGE_400))),
+            // the same box the player collides with
+            Hitbox {
+                half_extents: Vec3::new(PLAYER_HALF_WIDTH, PLAYER_HEIGHT / 2.0, PLAYER_HALF_WIDTH),
+            },
+            body_transform(state.translation, state.rotation),
+        ));
+    }
+}
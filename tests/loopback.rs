@@ -0,0 +1,288 @@
+//! A server and two clients in one process, talking over loopback TCP.
+
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use guncruft::{
+    environment::{
+        block::BlockType,
+        engine::Engine,
+        generator::FlatGenerator,
+        plugin::EnvironmentPlugin,
+        region::WorldStorage,
+        scanner::{RenderDistance, ScannerPlugin},
+    },
+    network::{
+        protocol::{ClientMessage, Connection, PROTOCOL_VERSION, ServerMessage},
+        server::{NetServer, ServerPlugin},
+    },
+    player::{
+        fps_movement::{MovementInput, MovementMode, SPEED, SPRINT_FACTOR},
+        player::SPAWN_TRANSLATION,
+    },
+};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+struct TestClient {
+    connection: Connection,
+    received: Vec<ServerMessage>,
+}
+
+impl TestClient {
+    fn connect(server: &App) -> Self {
+        let addr = server.world().resource::<NetServer>().local_addr().unwrap();
+        Self {
+            connection: Connection::connect(addr).unwrap(),
+            received: Vec::new(),
+        }
+    }
+
+    fn pump(&mut self) {
+        self.connection.flush().unwrap();
+        let messages = self.connection.receive::<ServerMessage>().unwrap();
+        self.received.extend(messages);
+    }
+
+    fn send(&mut self, message: &ClientMessage) {
+        self.connection.send(message);
+    }
+
+    ///! the block the last edit of pos sent to this client set it to
+    fn edited_block(&self, pos: IVec3) -> Option<BlockType> {
+        self.received
+            .iter()
+            .filter_map(|message| match message {
+                ServerMessage::BlockEdits(edits) => Some(edits),
+                _ => None,
+            })
+            .flatten()
+            .filter(|(edit_pos, _)| *edit_pos == pos)
+            .map(|(_, block_type)| *block_type)
+            .last()
+    }
+
+    fn has_chunk(&self, chunk_pos: IVec3) -> bool {
+        self.received.iter().any(|message| {
+            matches!(message, ServerMessage::Chunk { chunk_pos: pos, .. } if *pos == chunk_pos)
+        })
+    }
+}
+
+///! a headless server on a free loopback port, over a flat world kept out of the save directory
+fn server() -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, TransformPlugin, AssetPlugin::default()));
+    app.insert_resource(RenderDistance(2));
+    app.add_plugins(EnvironmentPlugin);
+    app.add_plugins(ScannerPlugin);
+    app.add_plugins(ServerPlugin {
+        addr: "127.0.0.1:0".into(),
+    });
+
+    let world_dir = std::env::temp_dir().join(format!("guncruft-loopback-{}", std::process::id()));
+    let mut engine = app.world_mut().resource_mut::<Engine>();
+    engine.generator = Arc::new(FlatGenerator::default());
+    engine.storage = Arc::new(WorldStorage::new(world_dir));
+    app
+}
+
+///! run the server and the clients until done says so
+fn run_until(
+    server: &mut App,
+    clients: &mut [TestClient],
+    mut done: impl FnMut(&[TestClient]) -> bool,
+) {
+    let start = Instant::now();
+    while !done(clients) {
+        assert!(start.elapsed() < TIMEOUT, "timed out");
+        for client in clients.iter_mut() {
+            client.pump();
+        }
+        server.update();
+        thread::sleep(Duration::from_millis(2));
+    }
+}
+
+///! one input that holds the player still where it spawned
+fn stand_still(tick: u32) -> ClientMessage {
+    ClientMessage::Input {
+        tick,
+        input: MovementInput::default(),
+        mode: MovementMode::Fly { noclip: false },
+        rotation: Quat::IDENTITY,
+    }
+}
+
+#[test]
+fn clients_share_chunks_and_edits() {
+    let mut server = server();
+    let mut clients = [TestClient::connect(&server), TestClient::connect(&server)];
+
+    // both are welcomed with their own id
+    run_until(&mut server, &mut clients, |clients| {
+        clients
+            .iter()
+            .all(|client| matches!(client.received.first(), Some(ServerMessage::Welcome { .. })))
+    });
+    let ids = clients.each_ref().map(|client| match client.received[0] {
+        ServerMessage::Welcome { version, player_id } => {
+            assert_eq!(version, PROTOCOL_VERSION);
+            player_id
+        }
+        _ => unreachable!(),
+    });
+    assert_ne!(ids[0], ids[1]);
+
+    // the server moves both players, so it knows where they are
+    for client in clients.iter_mut() {
+        client.send(&stand_still(1));
+    }
+    run_until(&mut server, &mut clients, |clients| {
+        clients.iter().all(|client| {
+            client
+                .received
+                .iter()
+                .any(|message| matches!(message, ServerMessage::MovementAck { tick: 1, .. }))
+        })
+    });
+
+    // the chunks around spawn are streamed to both
+    let spawn_chunk = IVec3::new(-1, 0, 0);
+    let far_chunk = IVec3::ZERO;
+    run_until(&mut server, &mut clients, |clients| {
+        clients
+            .iter()
+            .all(|client| client.has_chunk(spawn_chunk) && client.has_chunk(far_chunk))
+    });
+
+    // an edit within reach is applied and rebroadcast to everyone holding the chunk
+    let near = SPAWN_TRANSLATION.floor().as_ivec3() + IVec3::Y;
+    clients[0].send(&ClientMessage::SetBlock {
+        pos: near,
+        block_type: BlockType::DIRT,
+    });
+    run_until(&mut server, &mut clients, |clients| {
+        clients
+            .iter()
+            .all(|client| client.edited_block(near) == Some(BlockType::DIRT))
+    });
+    let engine = server.world().resource::<Engine>();
+    assert_eq!(
+        engine.get_block(near).map(|block| block.block_type),
+        Some(BlockType::DIRT)
+    );
+
+    // an edit out of reach is refused, the sender is told what the block still is
+    let far = SPAWN_TRANSLATION.floor().as_ivec3() + IVec3::new(20, 0, 0);
+    assert_eq!(far.div_euclid(IVec3::splat(32)), far_chunk);
+    clients[0].send(&ClientMessage::SetBlock {
+        pos: far,
+        block_type: BlockType::DIRT,
+    });
+    run_until(&mut server, &mut clients, |clients| {
+        clients[0].edited_block(far).is_some()
+    });
+    assert_eq!(clients[0].edited_block(far), Some(BlockType::AIR));
+    assert_eq!(clients[1].edited_block(far), None);
+    let engine = server.world().resource::<Engine>();
+    assert_eq!(
+        engine.get_block(far).map(|block| block.block_type),
+        Some(BlockType::AIR)
+    );
+}
+
+#[test]
+fn server_limits_movement() {
+    let mut server = server();
+    let mut clients = [TestClient::connect(&server)];
+
+    // far faster than the keys go, through walls
+    let input = MovementInput {
+        velocity: Vec3::new(1000.0, 1000.0, 0.0),
+        jump: false,
+    };
+    for tick in 1..=4 {
+        clients[0].send(&ClientMessage::Input {
+            tick,
+            input,
+            mode: MovementMode::Fly { noclip: true },
+            rotation: Quat::IDENTITY,
+        });
+    }
+    let ack = |client: &TestClient| {
+        client.received.iter().find_map(|message| match message {
+            ServerMessage::MovementAck { tick: 4, state } => Some(*state),
+            _ => None,
+        })
+    };
+    run_until(&mut server, &mut clients, |clients| {
+        ack(&clients[0]).is_some()
+    });
+
+    let state = ack(&clients[0]).unwrap();
+    let dt = server
+        .world()
+        .resource::<Time<Fixed>>()
+        .timestep()
+        .as_secs_f32();
+    let max_distance = 4.0 * SPEED * SPRINT_FACTOR * dt + 1e-4;
+    let moved = state.translation - SPAWN_TRANSLATION;
+    assert!(moved.x > 0.0 && moved.x <= max_distance, "moved {moved}");
+    assert!(moved.y <= max_distance, "moved {moved}");
+}
+
+#[test]
+fn server_paces_input_bursts() {
+    let mut server = server();
+    let mut clients = [TestClient::connect(&server)];
+
+    // a second's worth of inputs at once, and some of them again
+    let input = MovementInput {
+        velocity: Vec3::new(SPEED * SPRINT_FACTOR, 0.0, 0.0),
+        jump: false,
+    };
+    for tick in (1..=64).chain(1..=64) {
+        clients[0].send(&ClientMessage::Input {
+            tick,
+            input,
+            mode: MovementMode::Fly { noclip: false },
+            rotation: Quat::IDENTITY,
+        });
+    }
+    let last_ack = |client: &TestClient| {
+        client
+            .received
+            .iter()
+            .filter_map(|message| match message {
+                ServerMessage::MovementAck { tick, state } => Some((*tick, *state)),
+                _ => None,
+            })
+            .last()
+    };
+    run_until(&mut server, &mut clients, |clients| {
+        last_ack(&clients[0]).is_some()
+    });
+    let start = server.world().resource::<Time<Fixed>>().elapsed();
+    run_until(&mut server, &mut clients, |clients| {
+        last_ack(&clients[0]).is_some_and(|(tick, _)| tick >= 8)
+    });
+
+    // no faster than the server's clock, with the steps a client may save up
+    let fixed_time = server.world().resource::<Time<Fixed>>();
+    let dt = fixed_time.timestep().as_secs_f32();
+    let elapsed = (fixed_time.elapsed() - start).as_secs_f32();
+    let (tick, state) = last_ack(&clients[0]).unwrap();
+    let moved = state.translation - SPAWN_TRANSLATION;
+    let max_distance = (elapsed + 9.0 * dt) * SPEED * SPRINT_FACTOR + 1e-4;
+    assert!(tick < 64, "replayed {tick} inputs at once");
+    assert!(moved.x <= max_distance, "moved {moved} in {elapsed}s");
+    assert!(
+        (moved.x - tick as f32 * SPEED * SPRINT_FACTOR * dt).abs() < 1e-3,
+        "replayed inputs more than once, moved {moved}"
+    );
+}